@@ -0,0 +1,5 @@
+DROP TABLE post_deletions;
+
+ALTER TABLE posts
+  DROP COLUMN created_at,
+  DROP COLUMN updated_at;
@@ -0,0 +1,11 @@
+-- Timestamps are in UTC, which every connection sets as its time zone.
+ALTER TABLE posts
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+-- Deleted posts leave no `updated_at` behind, so remember when the last one went away to tell
+-- whether the feeds changed.
+CREATE TABLE post_deletions (
+  id INTEGER NOT NULL PRIMARY KEY CHECK (id = 1),
+  deleted_at TIMESTAMP NOT NULL
+);
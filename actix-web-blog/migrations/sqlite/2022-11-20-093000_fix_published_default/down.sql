@@ -0,0 +1,10 @@
+CREATE TABLE posts_old (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  title VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  published  BOOLEAN NOT NULL DEFAULT 'f'
+);
+INSERT INTO posts_old (id, title, body, published)
+SELECT id, title, body, published FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_old RENAME TO posts;
@@ -0,0 +1,12 @@
+-- `DEFAULT 'f'` stored the text 'f', which SQLite doesn't consider equal to false (0), so
+-- filtering on `published` skipped every post created without it.
+CREATE TABLE posts_new (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  title VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  published BOOLEAN NOT NULL DEFAULT 0
+);
+INSERT INTO posts_new (id, title, body, published)
+SELECT id, title, body, published IN (1, 't', 'true') FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_new RENAME TO posts;
@@ -0,0 +1,12 @@
+DROP TABLE post_deletions;
+
+CREATE TABLE posts_old (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  title VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  published BOOLEAN NOT NULL DEFAULT 0
+);
+INSERT INTO posts_old (id, title, body, published)
+SELECT id, title, body, published FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_old RENAME TO posts;
@@ -0,0 +1,20 @@
+-- SQLite can't add columns defaulting to the current time, so rebuild the table.
+CREATE TABLE posts_new (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  title VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  published BOOLEAN NOT NULL DEFAULT 0,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO posts_new (id, title, body, published)
+SELECT id, title, body, published FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_new RENAME TO posts;
+
+-- Deleted posts leave no `updated_at` behind, so remember when the last one went away to tell
+-- whether the feeds changed.
+CREATE TABLE post_deletions (
+  id INTEGER NOT NULL PRIMARY KEY CHECK (id = 1),
+  deleted_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,133 @@
+use diesel::connection::SimpleConnection;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{self, ManageConnection, R2D2Connection};
+use diesel::sqlite::SqliteConnection;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+/// The two backends differ in their DDL, so each has its own migrations.
+pub const SQLITE_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/sqlite");
+pub const POSTGRES_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/postgres");
+
+/// A connection to either of the supported databases.
+///
+/// Queries have to stick to what both backends support, e.g. no `ON CONFLICT`.
+#[derive(diesel::MultiConnection)]
+pub enum AnyConnection {
+    Postgresql(PgConnection),
+    Sqlite(SqliteConnection),
+}
+
+impl AnyConnection {
+    /// Run the pending migrations of this connection's backend.
+    pub fn migrate(&mut self) -> anyhow::Result<()> {
+        let res = match self {
+            AnyConnection::Postgresql(conn) => {
+                conn.run_pending_migrations(POSTGRES_MIGRATIONS).map(drop)
+            }
+            AnyConnection::Sqlite(conn) => conn.run_pending_migrations(SQLITE_MIGRATIONS).map(drop),
+        };
+
+        res.map_err(|e| anyhow::anyhow!("Failed to run migrations: {}", e))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Postgres,
+    Sqlite,
+}
+
+impl Backend {
+    /// `postgres://` and `postgresql://` URLs are Postgres, anything else is an SQLite database.
+    pub fn from_url(database_url: &str) -> Self {
+        if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+            Backend::Postgres
+        } else {
+            Backend::Sqlite
+        }
+    }
+}
+
+/// Connects to the backend of the database URL.
+///
+/// `ConnectionManager<AnyConnection>` would try each backend in turn, so a Postgres server that
+/// is down would get us an SQLite database in a file named like its URL.
+pub struct AnyConnectionManager {
+    backend: Backend,
+    database_url: String,
+}
+
+impl AnyConnectionManager {
+    pub fn new(database_url: &str) -> Self {
+        Self {
+            backend: Backend::from_url(database_url),
+            database_url: database_url.to_string(),
+        }
+    }
+}
+
+impl ManageConnection for AnyConnectionManager {
+    type Connection = AnyConnection;
+    type Error = r2d2::Error;
+
+    fn connect(&self) -> Result<AnyConnection, r2d2::Error> {
+        match self.backend {
+            Backend::Postgres => {
+                let mut conn = PgConnection::establish(&self.database_url)
+                    .map_err(r2d2::Error::ConnectionError)?;
+                // `TIMESTAMP` columns get `CURRENT_TIMESTAMP` in the session's time zone, while
+                // SQLite's is always UTC.
+                conn.batch_execute("SET TIME ZONE 'UTC'")
+                    .map_err(r2d2::Error::QueryError)?;
+                Ok(AnyConnection::Postgresql(conn))
+            }
+            Backend::Sqlite => SqliteConnection::establish(&self.database_url)
+                .map(AnyConnection::Sqlite)
+                .map_err(r2d2::Error::ConnectionError),
+        }
+    }
+
+    fn is_valid(&self, conn: &mut AnyConnection) -> Result<(), r2d2::Error> {
+        conn.ping().map_err(r2d2::Error::QueryError)
+    }
+
+    fn has_broken(&self, conn: &mut AnyConnection) -> bool {
+        std::thread::panicking() || conn.is_broken()
+    }
+}
+
+/// Create the Postgres database of `database_url` unless it exists already.
+#[cfg(test)]
+pub fn create_postgres_database(database_url: &str) -> anyhow::Result<()> {
+    if PgConnection::establish(database_url).is_ok() {
+        return Ok(());
+    }
+
+    let (server_url, name) = database_url
+        .rsplit_once('/')
+        .ok_or_else(|| anyhow::anyhow!("No database name in {}", database_url))?;
+    let mut conn = PgConnection::establish(&format!("{}/postgres", server_url))?;
+    conn.batch_execute(&format!(r#"CREATE DATABASE "{}""#, name))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_url() {
+        assert_eq!(
+            Backend::from_url("postgres://postgres@localhost/blog"),
+            Backend::Postgres
+        );
+        assert_eq!(
+            Backend::from_url("postgresql://localhost/blog"),
+            Backend::Postgres
+        );
+        assert_eq!(Backend::from_url("blog.db"), Backend::Sqlite);
+        assert_eq!(Backend::from_url(":memory:"), Backend::Sqlite);
+    }
+}
@@ -1,33 +1,183 @@
+use std::collections::BTreeMap;
+
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use diesel::result::DatabaseErrorKind;
+use serde::Serialize;
+
+/// How long clients should wait before retrying when every database connection is busy.
+const RETRY_AFTER_SECS: u32 = 5;
+
 #[derive(thiserror::Error, Debug)]
 pub enum ApiError {
     #[error("Post not found")]
     NotFound,
+    /// The request couldn't be parsed, e.g. malformed JSON or query string.
+    #[error("{0}")]
+    BadRequest(String),
+    /// Messages for each invalid field.
+    #[error("Validation failed")]
+    Validation(BTreeMap<&'static str, Vec<String>>),
+    #[error("Conflicts with an existing post")]
+    Conflict,
+    /// No database connection became available in time.
+    #[error("Service temporarily unavailable")]
+    Unavailable,
     #[error(transparent)]
-    Other(anyhow::Error),
+    Internal(anyhow::Error),
+}
+
+impl ApiError {
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound => "not_found",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Conflict => "conflict",
+            ApiError::Unavailable => "unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
 }
 
 macro_rules! impl_from_trait {
     ($etype: ty) => {
         impl From<$etype> for ApiError {
             fn from(e: $etype) -> Self {
-                ApiError::Other(anyhow::anyhow!(e))
+                ApiError::Internal(anyhow::anyhow!(e))
             }
         }
     };
 }
 
 impl_from_trait!(diesel::r2d2::Error);
-impl_from_trait!(diesel::r2d2::PoolError);
-impl_from_trait!(diesel::result::Error);
 impl_from_trait!(actix_web::error::BlockingError);
 
-use actix_web::{HttpResponse, ResponseError};
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::NotFound => ApiError::NotFound,
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                ApiError::Conflict
+            }
+            e => ApiError::Internal(anyhow::anyhow!(e)),
+        }
+    }
+}
+
+/// r2d2 only fails to hand out a connection when it timed out waiting for one.
+impl From<diesel::r2d2::PoolError> for ApiError {
+    fn from(e: diesel::r2d2::PoolError) -> Self {
+        tracing::warn!(error = %e, "database pool exhausted");
+        ApiError::Unavailable
+    }
+}
+
+/// The JSON body of every error response.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorBody<'a> {
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<&'a BTreeMap<&'static str, Vec<String>>>,
+    /// Logged along with the cause of internal errors, which isn't sent to clients.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+}
 
 impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Conflict => StatusCode::CONFLICT,
+            ApiError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
     fn error_response(&self) -> HttpResponse {
+        let mut body = ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            fields: None,
+            correlation_id: None,
+        };
+
         match self {
-            ApiError::NotFound => HttpResponse::NotFound().finish(),
-            ApiError::Other(_) => HttpResponse::ServiceUnavailable().finish(),
+            ApiError::Validation(fields) => body.fields = Some(fields),
+            ApiError::Internal(e) => {
+                let correlation_id = uuid::Uuid::new_v4().to_string();
+                tracing::error!(%correlation_id, error = ?e, "internal error");
+                body.message = "Internal server error".to_string();
+                body.correlation_id = Some(correlation_id);
+            }
+            _ => (),
+        }
+
+        let mut res = HttpResponse::build(self.status_code());
+        if let ApiError::Unavailable = self {
+            res.insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS));
         }
+        res.json(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use serde_json::{json, Value};
+
+    async fn body(e: ApiError) -> Value {
+        let bytes = to_bytes(e.error_response().into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_diesel_errors() {
+        let unique = diesel::result::Error::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            Box::new("UNIQUE constraint failed".to_string()),
+        );
+        let e = ApiError::from(unique);
+        assert_eq!(e.status_code(), StatusCode::CONFLICT);
+        assert_eq!(
+            body(e).await,
+            json!({ "code": "conflict", "message": "Conflicts with an existing post" })
+        );
+
+        let e = ApiError::from(diesel::result::Error::RollbackTransaction);
+        assert_eq!(e.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = body(e).await;
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["message"], "Internal server error");
+        assert!(body["correlationId"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn test_unavailable() {
+        let res = ApiError::Unavailable.error_response();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "5");
+    }
+
+    #[actix_web::test]
+    async fn test_validation() {
+        let e = ApiError::Validation(BTreeMap::from([(
+            "title",
+            vec!["must not be blank".to_string()],
+        )]));
+        assert_eq!(
+            body(e).await,
+            json!({
+                "code": "validation_failed",
+                "message": "Validation failed",
+                "fields": { "title": ["must not be blank"] },
+            })
+        );
     }
 }
@@ -0,0 +1,178 @@
+use std::time::{Duration, SystemTime};
+
+use actix_web::http::header::{self, HttpDate};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use atom_syndication::{ContentBuilder, EntryBuilder, FeedBuilder, FixedDateTime, LinkBuilder};
+use chrono::{NaiveDateTime, Utc};
+use rss::{ChannelBuilder, Guid, ItemBuilder};
+
+use crate::repository::Post;
+
+/// How many of the latest posts the Atom and RSS feeds list.
+const FEED_LEN: usize = 20;
+
+/// Where the blog is served from, for the absolute links of feeds and the sitemap.
+pub struct Site {
+    pub url: String,
+    pub title: String,
+}
+
+impl Site {
+    /// Read `SITE_URL` and `SITE_TITLE`.
+    pub fn from_env() -> Self {
+        let url = std::env::var("SITE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
+        let title = std::env::var("SITE_TITLE").unwrap_or_else(|_| "actix-web-blog".to_string());
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            title,
+        }
+    }
+
+    fn post_url(&self, post: &Post) -> String {
+        format!("{}/posts/{}", self.url, post.id)
+    }
+}
+
+/// An Atom feed of the latest of the published `posts`, which are ordered by id.
+pub fn atom(site: &Site, posts: &[Post], last_modified: Option<NaiveDateTime>) -> String {
+    let entries = latest(posts)
+        .map(|post| {
+            let url = site.post_url(post);
+            EntryBuilder::default()
+                .title(post.title.as_str())
+                .id(url.as_str())
+                .updated(utc(post.updated_at))
+                .published(Some(utc(post.created_at)))
+                .links(vec![LinkBuilder::default().href(url).build()])
+                .content(Some(
+                    ContentBuilder::default()
+                        .value(Some(post.body.clone()))
+                        .content_type(Some("text".to_string()))
+                        .build(),
+                ))
+                .build()
+        })
+        .collect::<Vec<_>>();
+
+    FeedBuilder::default()
+        .title(site.title.as_str())
+        .id(format!("{}/", site.url))
+        .updated(last_modified.map_or_else(|| Utc::now().into(), utc))
+        .links(vec![
+            LinkBuilder::default()
+                .href(format!("{}/feed.xml", site.url))
+                .rel("self")
+                .build(),
+            LinkBuilder::default()
+                .href(format!("{}/", site.url))
+                .build(),
+        ])
+        .entries(entries)
+        .build()
+        .to_string()
+}
+
+/// An RSS 2.0 feed of the latest of the published `posts`, which are ordered by id.
+pub fn rss(site: &Site, posts: &[Post], last_modified: Option<NaiveDateTime>) -> String {
+    let items = latest(posts)
+        .map(|post| {
+            let url = site.post_url(post);
+            ItemBuilder::default()
+                .title(Some(post.title.clone()))
+                .link(Some(url.clone()))
+                .guid(Some(Guid {
+                    value: url,
+                    permalink: true,
+                }))
+                .pub_date(Some(utc(post.created_at).to_rfc2822()))
+                .description(Some(post.body.clone()))
+                .build()
+        })
+        .collect::<Vec<_>>();
+
+    ChannelBuilder::default()
+        .title(site.title.as_str())
+        .link(format!("{}/", site.url))
+        .description(format!("Latest posts of {}", site.title))
+        .last_build_date(last_modified.map(|time| utc(time).to_rfc2822()))
+        .items(items)
+        .build()
+        .to_string()
+}
+
+/// A sitemap listing every published post.
+pub fn sitemap(site: &Site, posts: &[Post]) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#,
+    ));
+
+    for post in posts {
+        xml.push_str(&format!(
+            "<url><loc>{}</loc><lastmod>{}</lastmod></url>",
+            escape(&site.post_url(post)),
+            utc(post.updated_at).to_rfc3339(),
+        ));
+    }
+
+    xml.push_str("</urlset>");
+    xml
+}
+
+/// `304 Not Modified`, if the client's `If-Modified-Since` is still current.
+pub fn not_modified(
+    req: &HttpRequest,
+    last_modified: Option<NaiveDateTime>,
+) -> Option<HttpResponse> {
+    let last_modified = last_modified?;
+    let header::IfModifiedSince(since) = req.get_header::<header::IfModifiedSince>()?;
+
+    if system_time(last_modified) > SystemTime::from(since) {
+        return None;
+    }
+
+    Some(
+        HttpResponse::NotModified()
+            .insert_header(last_modified_header(last_modified))
+            .finish(),
+    )
+}
+
+pub fn xml_response(
+    content_type: &'static str,
+    last_modified: Option<NaiveDateTime>,
+    body: String,
+) -> HttpResponse {
+    let mut res = HttpResponse::Ok();
+    if let Some(last_modified) = last_modified {
+        res.insert_header(last_modified_header(last_modified));
+    }
+    res.content_type(content_type).body(body)
+}
+
+/// HTTP dates only have second precision, which `If-Modified-Since` is compared at.
+fn last_modified_header(time: NaiveDateTime) -> header::LastModified {
+    header::LastModified(HttpDate::from(system_time(time)))
+}
+
+fn latest(posts: &[Post]) -> impl Iterator<Item = &Post> {
+    posts.iter().rev().take(FEED_LEN)
+}
+
+/// Timestamps are stored in UTC.
+fn utc(time: NaiveDateTime) -> FixedDateTime {
+    time.and_utc().fixed_offset()
+}
+
+fn system_time(time: NaiveDateTime) -> SystemTime {
+    let secs = time.and_utc().timestamp().max(0) as u64;
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
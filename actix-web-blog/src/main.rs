@@ -1,15 +1,27 @@
+mod db;
 mod error;
+mod feed;
 mod repository;
 mod schema;
 
 use actix_web::middleware::{Logger, NormalizePath};
-use actix_web::{web, App, HttpResponse, HttpServer};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
 use error::ApiError;
-use repository::{NewPost, Repository};
+use feed::Site;
+use repository::{NewPost, Repository, UpdatePost};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct ListPostsQuery {
+    published: Option<bool>,
+}
 
 #[actix_web::get("/posts")]
-async fn list_posts(repo: web::Data<Repository>) -> Result<HttpResponse, ApiError> {
-    let res = repo.list_posts().await?;
+async fn list_posts(
+    repo: web::Data<Repository>,
+    query: web::Query<ListPostsQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let res = repo.list_posts(query.published).await?;
     Ok(HttpResponse::Ok().json(res))
 }
 
@@ -33,22 +45,336 @@ async fn create_post(
     Ok(HttpResponse::Ok().json(post))
 }
 
+#[actix_web::put("/posts/{id}")]
+async fn update_post(
+    repo: web::Data<Repository>,
+    path: web::Path<i32>,
+    update_post: web::Json<UpdatePost>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let post = repo.update_post(id, update_post.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(post))
+}
+
+#[actix_web::delete("/posts/{id}")]
+async fn delete_post(
+    repo: web::Data<Repository>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    repo.delete_post(id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[actix_web::post("/posts/{id}/publish")]
+async fn publish_post(
+    repo: web::Data<Repository>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let post = repo.set_published(id, true).await?;
+    Ok(HttpResponse::Ok().json(post))
+}
+
+#[actix_web::post("/posts/{id}/unpublish")]
+async fn unpublish_post(
+    repo: web::Data<Repository>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let post = repo.set_published(id, false).await?;
+    Ok(HttpResponse::Ok().json(post))
+}
+
+#[actix_web::get("/feed.xml")]
+async fn atom_feed(
+    repo: web::Data<Repository>,
+    site: web::Data<Site>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let last_modified = repo.last_modified().await?;
+    if let Some(res) = feed::not_modified(&req, last_modified) {
+        return Ok(res);
+    }
+    let posts = repo.list_posts(Some(true)).await?;
+    let body = feed::atom(&site, &posts, last_modified);
+    Ok(feed::xml_response(
+        "application/atom+xml",
+        last_modified,
+        body,
+    ))
+}
+
+#[actix_web::get("/rss.xml")]
+async fn rss_feed(
+    repo: web::Data<Repository>,
+    site: web::Data<Site>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let last_modified = repo.last_modified().await?;
+    if let Some(res) = feed::not_modified(&req, last_modified) {
+        return Ok(res);
+    }
+    let posts = repo.list_posts(Some(true)).await?;
+    let body = feed::rss(&site, &posts, last_modified);
+    Ok(feed::xml_response(
+        "application/rss+xml",
+        last_modified,
+        body,
+    ))
+}
+
+#[actix_web::get("/sitemap.xml")]
+async fn sitemap(
+    repo: web::Data<Repository>,
+    site: web::Data<Site>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let last_modified = repo.last_modified().await?;
+    if let Some(res) = feed::not_modified(&req, last_modified) {
+        return Ok(res);
+    }
+    let posts = repo.list_posts(Some(true)).await?;
+    let body = feed::sitemap(&site, &posts);
+    Ok(feed::xml_response("application/xml", last_modified, body))
+}
+
+fn config(cfg: &mut web::ServiceConfig) {
+    // Render extractor errors the same way as the handlers' own ones.
+    cfg.app_data(
+        web::JsonConfig::default().error_handler(|e, _| ApiError::BadRequest(e.to_string()).into()),
+    )
+    .app_data(
+        web::QueryConfig::default()
+            .error_handler(|e, _| ApiError::BadRequest(e.to_string()).into()),
+    )
+    .app_data(web::PathConfig::default().error_handler(|_, _| ApiError::NotFound.into()));
+
+    cfg.service(list_posts)
+        .service(get_post)
+        .service(create_post)
+        .service(update_post)
+        .service(delete_post)
+        .service(publish_post)
+        .service(unpublish_post)
+        .service(atom_feed)
+        .service(rss_feed)
+        .service(sitemap);
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     tracing_subscriber::fmt::init();
-    let database_url = std::env::var("DATABASE_URL").unwrap();
-    let repo = web::Data::new(Repository::new(&database_url));
+    // A `postgres://` URL or an SQLite file; `:memory:` works too, but the posts are gone when
+    // the server stops.
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let repo =
+        web::Data::new(Repository::new(&database_url).expect("Failed to set up the database"));
+    // The absolute URLs in the feeds and the sitemap start with `SITE_URL`.
+    let site = web::Data::new(Site::from_env());
 
     HttpServer::new(move || {
         App::new()
             .app_data(repo.clone())
+            .app_data(site.clone())
             .wrap(Logger::default())
             .wrap(NormalizePath::trim())
-            .service(list_posts)
-            .service(get_post)
-            .service(create_post)
+            .configure(config)
     })
     .bind(("0.0.0.0", 8080))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test;
+    use serde_json::{json, Value};
+
+    macro_rules! app {
+        ($repo:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::new($repo))
+                    .app_data(web::Data::new(Site {
+                        url: "http://blog.test".to_string(),
+                        title: "Test blog".to_string(),
+                    }))
+                    .wrap(NormalizePath::trim())
+                    .configure(config),
+            )
+            .await
+        };
+    }
+
+    #[actix_web::test]
+    async fn test_post_lifecycle() {
+        for repo in Repository::test_backends() {
+            let app = app!(repo);
+
+            let req = test::TestRequest::post()
+                .uri("/posts")
+                .set_json(json!({ "title": "first", "body": "hello" }))
+                .to_request();
+            let post: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(post["title"], "first");
+            assert_eq!(post["published"], false);
+            let uri = format!("/posts/{}", post["id"]);
+
+            let req = test::TestRequest::put()
+                .uri(&uri)
+                .set_json(json!({ "title": "first!", "body": "hello again" }))
+                .to_request();
+            let post: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(post["title"], "first!");
+
+            let req = test::TestRequest::post()
+                .uri(&format!("{}/publish", uri))
+                .to_request();
+            let post: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(post["published"], true);
+
+            let req = test::TestRequest::get()
+                .uri("/posts?published=true")
+                .to_request();
+            let posts: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(posts.as_array().unwrap().len(), 1);
+
+            let req = test::TestRequest::get()
+                .uri("/posts?published=false")
+                .to_request();
+            let posts: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(posts, json!([]));
+
+            let req = test::TestRequest::delete().uri(&uri).to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+            let req = test::TestRequest::get().uri(&uri).to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_error_bodies() {
+        for repo in Repository::test_backends() {
+            let app = app!(repo);
+
+            let req = test::TestRequest::post()
+                .uri("/posts")
+                .set_json(json!({ "title": "", "body": "hello" }))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            let body: Value = test::read_body_json(res).await;
+            assert_eq!(body["code"], "validation_failed");
+            assert_eq!(body["fields"]["title"], json!(["must not be blank"]));
+
+            let req = test::TestRequest::post()
+                .uri("/posts")
+                .insert_header(("content-type", "application/json"))
+                .set_payload("{")
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let body: Value = test::read_body_json(res).await;
+            assert_eq!(body["code"], "bad_request");
+
+            let req = test::TestRequest::get()
+                .uri("/posts?published=maybe")
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+            let req = test::TestRequest::get().uri("/posts/first").to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            let body: Value = test::read_body_json(res).await;
+            assert_eq!(
+                body,
+                json!({ "code": "not_found", "message": "Post not found" })
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_not_found() {
+        for repo in Repository::test_backends() {
+            let app = app!(repo);
+
+            let requests = [
+                test::TestRequest::get().uri("/posts/42"),
+                test::TestRequest::put()
+                    .uri("/posts/42")
+                    .set_json(json!({ "title": "title", "body": "body" })),
+                test::TestRequest::delete().uri("/posts/42"),
+                test::TestRequest::post().uri("/posts/42/publish"),
+                test::TestRequest::post().uri("/posts/42/unpublish"),
+            ];
+
+            for req in requests {
+                let res = test::call_service(&app, req.to_request()).await;
+                assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_feeds() {
+        for repo in Repository::test_backends() {
+            let app = app!(repo);
+
+            let mut urls = Vec::new();
+            for title in ["draft", "published"] {
+                let req = test::TestRequest::post()
+                    .uri("/posts")
+                    .set_json(json!({ "title": title, "body": "hello" }))
+                    .to_request();
+                let post: Value = test::call_and_read_body_json(&app, req).await;
+                urls.push(format!("/posts/{}", post["id"]));
+            }
+            let [draft, published] = &urls[..] else {
+                unreachable!()
+            };
+            let req = test::TestRequest::post()
+                .uri(&format!("{}/publish", published))
+                .to_request();
+            test::call_service(&app, req).await;
+
+            for uri in ["/feed.xml", "/rss.xml", "/sitemap.xml"] {
+                let req = test::TestRequest::get().uri(uri).to_request();
+                let res = test::call_service(&app, req).await;
+                assert_eq!(res.status(), StatusCode::OK);
+                let last_modified = res.headers().get(header::LAST_MODIFIED).unwrap().clone();
+
+                let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+                assert!(body.contains(&format!("http://blog.test{}<", published)));
+                assert!(!body.contains(&format!("http://blog.test{}<", draft)));
+
+                let req = test::TestRequest::get()
+                    .uri(uri)
+                    .insert_header((header::IF_MODIFIED_SINCE, last_modified))
+                    .to_request();
+                let res = test::call_service(&app, req).await;
+                assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+            }
+
+            let req = test::TestRequest::get().uri("/feed.xml").to_request();
+            let body = test::call_and_read_body(&app, req).await;
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            assert!(body.contains("<title>published</title>"));
+            assert!(!body.contains("<title>draft</title>"));
+
+            // A client that fetched the feed before anything was posted gets it again.
+            let req = test::TestRequest::get()
+                .uri("/rss.xml")
+                .insert_header((header::IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT"))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+}
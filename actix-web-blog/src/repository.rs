@@ -1,29 +1,58 @@
+use crate::db::AnyConnectionManager;
 use crate::error::ApiError;
 use crate::schema::*;
 use actix_web::web;
+use chrono::NaiveDateTime;
+use diesel::dsl::{max, now};
 use diesel::prelude::*;
-use diesel::r2d2::{self, ConnectionManager};
-use diesel::sqlite::SqliteConnection;
+use diesel::r2d2;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-type DbPool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+type DbPool = r2d2::Pool<AnyConnectionManager>;
+
+/// A database that lives as long as its connection, e.g. for tests.
+const IN_MEMORY: &str = ":memory:";
+
+const MAX_TITLE_LEN: usize = 200;
 
 pub struct Repository {
     pool: DbPool,
 }
 
 impl Repository {
-    pub fn new(database_url: &str) -> Self {
-        let manager = ConnectionManager::<SqliteConnection>::new(database_url);
-        let pool = r2d2::Pool::builder()
-            .build(manager)
-            .expect("Failed to create a pool.");
-        Self { pool }
+    /// Connect to `database_url` and run the pending migrations, so the database needs no setup.
+    ///
+    /// `postgres://` URLs are Postgres databases, anything else is an SQLite database.
+    ///
+    /// With `:memory:`, the pool keeps a single connection open forever, since every connection
+    /// would get a database of its own and the database goes away with its connection.
+    pub fn new(database_url: &str) -> anyhow::Result<Self> {
+        let manager = AnyConnectionManager::new(database_url);
+        let mut builder = r2d2::Pool::builder();
+        if database_url == IN_MEMORY {
+            builder = builder.max_size(1).idle_timeout(None).max_lifetime(None);
+        }
+
+        Self::with_pool(builder.build(manager)?)
+    }
+
+    fn with_pool(pool: DbPool) -> anyhow::Result<Self> {
+        pool.get()?.migrate()?;
+        Ok(Self { pool })
     }
 
-    pub async fn list_posts(&self) -> Result<Vec<Post>, ApiError> {
+    /// List all posts, or only the (un)published ones.
+    pub async fn list_posts(&self, published: Option<bool>) -> Result<Vec<Post>, ApiError> {
         let mut conn = self.pool.get()?;
-        let res = web::block(move || posts::table.load(&mut conn)).await??;
+        let res = web::block(move || {
+            let mut query = posts::table.order(posts::id).into_boxed();
+            if let Some(published) = published {
+                query = query.filter(posts::published.eq(published));
+            }
+            query.load(&mut conn)
+        })
+        .await??;
 
         Ok(res)
     }
@@ -38,6 +67,7 @@ impl Repository {
     }
 
     pub async fn create_post(&self, new_post: NewPost) -> Result<Post, ApiError> {
+        validate_post(&new_post.title, &new_post.body)?;
         let mut conn = self.pool.get()?;
         let post = web::block(move || {
             diesel::insert_into(posts::table)
@@ -48,17 +78,277 @@ impl Repository {
 
         Ok(post)
     }
+
+    pub async fn update_post(&self, id: i32, update_post: UpdatePost) -> Result<Post, ApiError> {
+        validate_post(&update_post.title, &update_post.body)?;
+        let mut conn = self.pool.get()?;
+        let post = web::block(move || {
+            diesel::update(posts::table.find(id))
+                .set((update_post, posts::updated_at.eq(now)))
+                .get_result(&mut conn)
+                .optional()
+        })
+        .await??
+        .ok_or(ApiError::NotFound)?;
+
+        Ok(post)
+    }
+
+    pub async fn delete_post(&self, id: i32) -> Result<(), ApiError> {
+        let mut conn = self.pool.get()?;
+        let deleted = web::block(move || {
+            conn.transaction(|conn| {
+                let deleted = diesel::delete(posts::table.find(id)).execute(conn)?;
+                if deleted > 0 {
+                    // SQLite and Postgres share no upsert syntax that `AnyConnection` supports.
+                    let updated = diesel::update(post_deletions::table.find(1))
+                        .set(post_deletions::deleted_at.eq(now))
+                        .execute(conn)?;
+                    if updated == 0 {
+                        diesel::insert_into(post_deletions::table)
+                            .values((post_deletions::id.eq(1), post_deletions::deleted_at.eq(now)))
+                            .execute(conn)?;
+                    }
+                }
+                QueryResult::Ok(deleted)
+            })
+        })
+        .await??;
+
+        if deleted == 0 {
+            return Err(ApiError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_published(&self, id: i32, published: bool) -> Result<Post, ApiError> {
+        let mut conn = self.pool.get()?;
+        let post = web::block(move || {
+            diesel::update(posts::table.find(id))
+                .set((posts::published.eq(published), posts::updated_at.eq(now)))
+                .get_result(&mut conn)
+                .optional()
+        })
+        .await??
+        .ok_or(ApiError::NotFound)?;
+
+        Ok(post)
+    }
+
+    /// When any post was last created, changed or deleted, or `None` if there never was one.
+    pub async fn last_modified(&self) -> Result<Option<NaiveDateTime>, ApiError> {
+        let mut conn = self.pool.get()?;
+        let res = web::block(move || {
+            let updated_at = posts::table
+                .select(max(posts::updated_at))
+                .first::<Option<NaiveDateTime>>(&mut conn)?;
+            let deleted_at = post_deletions::table
+                .select(post_deletions::deleted_at)
+                .first::<NaiveDateTime>(&mut conn)
+                .optional()?;
+            QueryResult::Ok(updated_at.max(deleted_at))
+        })
+        .await??;
+
+        Ok(res)
+    }
+}
+
+/// Check the fields of a new or updated post.
+fn validate_post(title: &str, body: &str) -> Result<(), ApiError> {
+    let mut fields = BTreeMap::new();
+
+    if title.trim().is_empty() {
+        fields.insert("title", vec!["must not be blank".to_string()]);
+    } else if title.chars().count() > MAX_TITLE_LEN {
+        let message = format!("must be at most {} characters", MAX_TITLE_LEN);
+        fields.insert("title", vec![message]);
+    }
+
+    if body.trim().is_empty() {
+        fields.insert("body", vec!["must not be blank".to_string()]);
+    }
+
+    if fields.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::Validation(fields))
+    }
+}
+
+#[cfg(test)]
+impl Repository {
+    /// An in-memory SQLite database and, when `TEST_POSTGRES_URL` is set, a Postgres one whose
+    /// changes are rolled back once the repository is dropped, so that tests running in parallel
+    /// don't see each other's posts.
+    pub fn test_backends() -> Vec<Self> {
+        let mut backends = vec![Self::new(IN_MEMORY).unwrap()];
+        if let Ok(database_url) = std::env::var("TEST_POSTGRES_URL") {
+            backends.push(Self::test_postgres(&database_url).unwrap());
+        }
+        backends
+    }
+
+    fn test_postgres(database_url: &str) -> anyhow::Result<Self> {
+        // Tests run in parallel, but only one of them should create the database and migrate it.
+        static SETUP: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+        let setup = SETUP.lock().unwrap_or_else(|e| e.into_inner());
+        crate::db::create_postgres_database(database_url)?;
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .build(AnyConnectionManager::new(database_url))?;
+        let repo = Self::with_pool(pool)?;
+        drop(setup);
+
+        // The pool's only connection stays in this transaction until it's dropped.
+        repo.pool.get()?.begin_test_transaction()?;
+
+        Ok(repo)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_conn() {
-        let database_url = std::env::var("DATABASE_URL").unwrap();
-        let repo = Repository::new(&database_url);
-        assert!(repo.pool.get().is_ok());
+    fn new_post(title: &str) -> NewPost {
+        NewPost {
+            title: title.to_string(),
+            body: "body".to_string(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_create_and_get_post() {
+        for repo in Repository::test_backends() {
+            let post = repo.create_post(new_post("first")).await.unwrap();
+            assert_eq!(post.title, "first");
+            assert!(!post.published);
+
+            let res = repo.get_post(post.id).await.unwrap();
+            assert_eq!(res.title, "first");
+            assert!(matches!(repo.get_post(42).await, Err(ApiError::NotFound)));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_update_and_delete_post() {
+        for repo in Repository::test_backends() {
+            let post = repo.create_post(new_post("first")).await.unwrap();
+
+            let update = UpdatePost {
+                title: "updated".to_string(),
+                body: "new body".to_string(),
+            };
+            let res = repo.update_post(post.id, update).await.unwrap();
+            assert_eq!(res.title, "updated");
+            assert_eq!(res.body, "new body");
+
+            let update = UpdatePost {
+                title: "updated".to_string(),
+                body: "new body".to_string(),
+            };
+            assert!(matches!(
+                repo.update_post(42, update).await,
+                Err(ApiError::NotFound)
+            ));
+
+            repo.delete_post(post.id).await.unwrap();
+            assert!(matches!(
+                repo.get_post(post.id).await,
+                Err(ApiError::NotFound)
+            ));
+            assert!(matches!(
+                repo.delete_post(post.id).await,
+                Err(ApiError::NotFound)
+            ));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_last_modified() {
+        for repo in Repository::test_backends() {
+            assert_eq!(repo.last_modified().await.unwrap(), None);
+
+            let post = repo.create_post(new_post("first")).await.unwrap();
+            let long_ago = chrono::NaiveDate::from_ymd_opt(2000, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap();
+            diesel::update(posts::table.find(post.id))
+                .set(posts::updated_at.eq(long_ago))
+                .execute(&mut repo.pool.get().unwrap())
+                .unwrap();
+            assert_eq!(repo.last_modified().await.unwrap(), Some(long_ago));
+
+            // Deleting the post changes the feeds too.
+            repo.delete_post(post.id).await.unwrap();
+            assert!(repo.last_modified().await.unwrap().unwrap() > long_ago);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_validate_post() {
+        for repo in Repository::test_backends() {
+            let blank = NewPost {
+                title: " ".to_string(),
+                body: "".to_string(),
+            };
+
+            match repo.create_post(blank).await {
+                Err(ApiError::Validation(fields)) => {
+                    assert_eq!(fields["title"], ["must not be blank"]);
+                    assert_eq!(fields["body"], ["must not be blank"]);
+                }
+                _ => panic!("expected a validation error"),
+            }
+
+            let post = repo.create_post(new_post("first")).await.unwrap();
+            let update = UpdatePost {
+                title: "x".repeat(MAX_TITLE_LEN + 1),
+                body: "body".to_string(),
+            };
+            assert!(matches!(
+                repo.update_post(post.id, update).await,
+                Err(ApiError::Validation(_))
+            ));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_publish_and_filter_posts() {
+        for repo in Repository::test_backends() {
+            let first = repo.create_post(new_post("first")).await.unwrap();
+            let second = repo.create_post(new_post("second")).await.unwrap();
+
+            let res = repo.set_published(first.id, true).await.unwrap();
+            assert!(res.published);
+
+            let titles = |posts: Vec<Post>| posts.into_iter().map(|p| p.title).collect::<Vec<_>>();
+            assert_eq!(
+                titles(repo.list_posts(None).await.unwrap()),
+                ["first", "second"]
+            );
+            assert_eq!(
+                titles(repo.list_posts(Some(true)).await.unwrap()),
+                ["first"]
+            );
+            assert_eq!(
+                titles(repo.list_posts(Some(false)).await.unwrap()),
+                ["second"]
+            );
+
+            repo.set_published(first.id, false).await.unwrap();
+            assert!(repo.list_posts(Some(true)).await.unwrap().is_empty());
+            assert!(matches!(
+                repo.set_published(second.id + 1, true).await,
+                Err(ApiError::NotFound)
+            ));
+        }
     }
 }
 
@@ -69,10 +359,19 @@ pub struct NewPost {
     body: String,
 }
 
-#[derive(Serialize, Queryable)]
-pub struct Post {
-    id: i32,
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = posts)]
+pub struct UpdatePost {
     title: String,
     body: String,
-    published: bool,
+}
+
+#[derive(Serialize, Queryable)]
+pub struct Post {
+    pub id: i32,
+    pub title: String,
+    pub body: String,
+    pub published: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
 }
@@ -1,10 +1,21 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    post_deletions (id) {
+        id -> Integer,
+        deleted_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     posts (id) {
         id -> Integer,
         title -> Text,
         body -> Text,
         published -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
+
+diesel::allow_tables_to_appear_in_same_query!(post_deletions, posts,);
@@ -1,5 +1,46 @@
 CREATE TABLE accounts (
   email VARCHAR PRIMARY KEY UNIQUE NOT NULL, CHECK (email <> ''),
   password VARCHAR NOT NULL,
-  active BOOL DEFAULT true
+  -- Set once the email address is confirmed.
+  active BOOL NOT NULL DEFAULT false,
+  -- Base32 TOTP secret, NULL unless two-factor authentication is on.
+  totp_secret VARCHAR,
+  -- Time step of the last TOTP code accepted, which can't be used again.
+  totp_last_step BIGINT,
+  -- Set after too many failed logins, until unlocked by email.
+  locked_at TIMESTAMPTZ
 );
+
+CREATE TABLE recovery_codes (
+  email VARCHAR NOT NULL REFERENCES accounts (email) ON DELETE CASCADE,
+  code_hash VARCHAR NOT NULL
+);
+
+CREATE INDEX recovery_codes_email ON recovery_codes (email);
+
+-- Single-use tokens of the links sent by email, stored hashed.
+CREATE TABLE account_tokens (
+  token_hash VARCHAR PRIMARY KEY,
+  email VARCHAR NOT NULL REFERENCES accounts (email) ON DELETE CASCADE,
+  purpose VARCHAR NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL
+);
+
+-- Failed logins by email and IP address. Only for SESSION_STORE=postgres.
+CREATE TABLE login_attempts (
+  key VARCHAR PRIMARY KEY,
+  failures INTEGER NOT NULL,
+  -- Seconds since the Unix epoch.
+  last_failure BIGINT NOT NULL
+);
+
+-- Only for SESSION_STORE=postgres.
+CREATE TABLE sessions (
+  id VARCHAR PRIMARY KEY,
+  -- The account the session is logged into, if any.
+  email VARCHAR REFERENCES accounts (email) ON DELETE CASCADE,
+  expires TIMESTAMPTZ,
+  data TEXT NOT NULL
+);
+
+CREATE INDEX sessions_email ON sessions (email);
@@ -20,6 +20,34 @@ pub struct AppConfig {
     pub redis_host: String,
     #[serde(default = "dafault_redis_port")]
     pub redis_port: String,
+    #[serde(default = "default_session_store")]
+    pub session_store: SessionStoreKind,
+    /// Seconds a session lasts without requests.
+    #[serde(default = "default_session_idle_timeout")]
+    pub session_idle_timeout: u64,
+    /// Seconds a session lasts at most after logging in.
+    #[serde(default = "default_session_absolute_timeout")]
+    pub session_absolute_timeout: u64,
+    /// Where the app is reachable, for the links in emails.
+    #[serde(default = "default_app_url")]
+    pub app_url: String,
+    /// The directory emails are written to instead of being sent.
+    #[serde(default = "default_mail_dir")]
+    pub mail_dir: String,
+    #[serde(default = "default_mail_from")]
+    pub mail_from: String,
+    /// Failed logins in a row that lock an account until it's unlocked by email.
+    #[serde(default = "default_login_lockout_threshold")]
+    pub login_lockout_threshold: u32,
+}
+
+/// Where sessions are kept: `memory` (lost on restart, for tests), `postgres` or `redis`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    Memory,
+    Postgres,
+    Redis,
 }
 
 fn default_environment() -> String {
@@ -57,3 +85,31 @@ fn dafault_redis_host() -> String {
 fn dafault_redis_port() -> String {
     "6379".to_string()
 }
+
+fn default_session_store() -> SessionStoreKind {
+    SessionStoreKind::Redis
+}
+
+fn default_session_idle_timeout() -> u64 {
+    30 * 60
+}
+
+fn default_session_absolute_timeout() -> u64 {
+    12 * 60 * 60
+}
+
+fn default_app_url() -> String {
+    "http://localhost:8000".to_string()
+}
+
+fn default_mail_dir() -> String {
+    "mail".to_string()
+}
+
+fn default_mail_from() -> String {
+    "App <noreply@localhost>".to_string()
+}
+
+fn default_login_lockout_threshold() -> u32 {
+    10
+}
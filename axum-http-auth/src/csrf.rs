@@ -0,0 +1,231 @@
+//! CSRF protection with synchronizer tokens.
+//!
+//! Sessions get a random token once a page renders it, into its forms and, through `_base.html`,
+//! a `csrf-token` meta tag. Requests that may change something must send it back, either as the
+//! `csrf_token` form field or in the `X-CSRF-Token` header.
+
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{FromRequest, FromRequestParts},
+    http::{request::Parts, Extensions, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_sessions::SessionHandle;
+use rand::RngCore;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::views::{ErrorTemplate, HtmlTemplate};
+
+pub(crate) const SESSION_KEY: &str = "csrf_token";
+const HEADER: &str = "x-csrf-token";
+
+/// The CSRF token of the current session, for templates to render.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(pub String);
+
+impl CsrfToken {
+    pub fn from_extensions(extensions: &Extensions) -> Self {
+        if let Some(UnstoredToken(used)) = extensions.get::<UnstoredToken>() {
+            used.store(true, Ordering::Relaxed);
+        }
+        extensions
+            .get::<CsrfToken>()
+            .cloned()
+            .expect("the CSRF layer must wrap every page")
+    }
+}
+
+/// Set on requests whose session has no token yet, to tell whether the one made up for it was
+/// rendered and so has to be stored.
+#[derive(Clone)]
+struct UnstoredToken(Arc<AtomicBool>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CsrfToken
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_extensions(&parts.extensions))
+    }
+}
+
+#[derive(Deserialize)]
+struct CsrfForm {
+    csrf_token: String,
+}
+
+/// Middleware rejecting requests other than `GET`, `HEAD` and `OPTIONS` that don't carry the
+/// session's CSRF token with `403 Forbidden`.
+///
+/// It has to be layered inside the `SessionLayer`, and only around pages: sessions without a
+/// token get one stored once a page renders it, after the handler is done with the session.
+pub async fn protect(mut req: Request<Body>, next: Next<Body>) -> Response {
+    let session = req
+        .extensions()
+        .get::<SessionHandle>()
+        .cloned()
+        .expect("the session layer must wrap the CSRF layer");
+
+    let stored = session.read().await.get::<String>(SESSION_KEY);
+    let token = stored.clone().unwrap_or_else(new_token);
+
+    if !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        let (parts, body) = req.into_parts();
+
+        let submitted = parts
+            .headers
+            .get(HEADER)
+            .and_then(|header| header.to_str().ok())
+            .map(str::to_owned);
+        let body = match Bytes::from_request(Request::new(body), &()).await {
+            Ok(body) => body,
+            Err(rejection) => return rejection.into_response(),
+        };
+        let submitted = submitted.or_else(|| {
+            serde_urlencoded::from_bytes::<CsrfForm>(&body)
+                .ok()
+                .map(|form| form.csrf_token)
+        });
+
+        // Without a stored token, nothing submitted can be right.
+        if stored.is_none() || !submitted.is_some_and(|submitted| tokens_match(&submitted, &token))
+        {
+            tracing::debug!(
+                "rejected {} {} without a valid CSRF token",
+                parts.method,
+                parts.uri
+            );
+            let template = ErrorTemplate {
+                title: "App - Forbidden".to_string(),
+                message: "This form has expired. Please go back, reload the page and try again."
+                    .to_string(),
+                csrf_token: token.clone(),
+            };
+            if stored.is_none() {
+                store_token(&session, &token).await;
+            }
+            return (StatusCode::FORBIDDEN, HtmlTemplate(template)).into_response();
+        }
+
+        req = Request::from_parts(parts, Body::from(body));
+    }
+
+    req.extensions_mut().insert(CsrfToken(token.clone()));
+    if stored.is_some() {
+        return next.run(req).await;
+    }
+
+    let used = Arc::new(AtomicBool::new(false));
+    req.extensions_mut().insert(UnstoredToken(used.clone()));
+    let res = next.run(req).await;
+    if used.load(Ordering::Relaxed) {
+        store_token(&session, &token).await;
+    }
+    res
+}
+
+async fn store_token(session: &SessionHandle, token: &str) {
+    session
+        .write()
+        .await
+        .insert(SESSION_KEY, token)
+        .expect("Session could not be created.");
+}
+
+fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Compare in constant time, so that response times don't give away how much of a guess is right.
+pub(crate) fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, routing::get, Extension, Router};
+    use axum_sessions::async_session::Session;
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+
+    async fn send(app: &Router, method: Method, uri: &str, token: Option<&str>) -> StatusCode {
+        let mut req = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            req = req.header(HEADER, token);
+        }
+        let req = req.body(Body::empty()).unwrap();
+        app.clone().oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_protect() {
+        let session: SessionHandle = Arc::new(RwLock::new(Session::new()));
+        let app = Router::new()
+            .route(
+                "/plain",
+                get(|| async { "plain" }).post(|| async { "posted" }),
+            )
+            .route("/page", get(|CsrfToken(token): CsrfToken| async { token }))
+            .layer(middleware::from_fn(protect))
+            .layer(Extension(session.clone()));
+        let stored = || async { session.read().await.get::<String>(SESSION_KEY) };
+
+        // Only pages rendering the token need one.
+        assert_eq!(
+            send(&app, Method::GET, "/plain", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(stored().await, None);
+        assert_eq!(
+            send(&app, Method::POST, "/plain", Some(&new_token())).await,
+            StatusCode::FORBIDDEN
+        );
+
+        assert_eq!(send(&app, Method::GET, "/page", None).await, StatusCode::OK);
+        let token = stored().await.unwrap();
+        assert_eq!(send(&app, Method::GET, "/page", None).await, StatusCode::OK);
+        assert_eq!(stored().await.unwrap(), token);
+
+        assert_eq!(
+            send(&app, Method::POST, "/plain", None).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&app, Method::POST, "/plain", Some(&new_token())).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&app, Method::POST, "/plain", Some(&token)).await,
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        let token = new_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, new_token());
+
+        assert!(tokens_match(&token, &token.clone()));
+        assert!(!tokens_match(&token, &new_token()));
+        assert!(!tokens_match(&token, &token[1..]));
+        assert!(!tokens_match(&token, ""));
+    }
+}
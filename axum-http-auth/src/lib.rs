@@ -1,2 +1,9 @@
 pub mod config;
+pub mod csrf;
+pub mod mailer;
+pub mod password;
+pub mod sessions;
+pub mod throttle;
+pub mod tokens;
+pub mod totp;
 pub mod views;
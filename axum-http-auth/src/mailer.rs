@@ -0,0 +1,154 @@
+//! Sending emails.
+//!
+//! The app only talks to the `Mailer` trait. `FileMailer` writes emails to a directory instead of
+//! sending them, for trying things out locally.
+
+use axum::async_trait;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Debug)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("failed to write email: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailError>;
+}
+
+/// Writes each email to a `.eml` file in `dir`, which mail clients can open.
+#[derive(Debug)]
+pub struct FileMailer {
+    dir: PathBuf,
+    from: String,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>, from: &str) -> Self {
+        Self {
+            dir: dir.into(),
+            from: from.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis());
+        let to: String = email
+            .to
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = self.dir.join(format!("{}-{}.eml", millis, to));
+
+        let contents = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+            self.from,
+            email.to,
+            email.subject,
+            email.body.replace('\n', "\r\n"),
+        );
+        tokio::fs::write(&path, contents).await?;
+        tracing::debug!("wrote email to {}", path.display());
+        Ok(())
+    }
+}
+
+/// The emails the app sends, with links back to it at `app_url`.
+#[derive(Clone)]
+pub struct Outbox {
+    mailer: Arc<dyn Mailer>,
+    app_url: String,
+}
+
+impl Outbox {
+    pub fn new(mailer: Arc<dyn Mailer>, app_url: &str) -> Self {
+        Self {
+            mailer,
+            app_url: app_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// The link activating the account of `to`, which signing up and logging into an inactive
+    /// account send.
+    pub async fn send_confirmation(&self, to: &str, token: &str) -> Result<(), MailError> {
+        self.mailer
+            .send(Email {
+                to: to.to_string(),
+                subject: "Confirm your email address".to_string(),
+                body: format!(
+                    "To activate your account, open this link:\n\n{}/confirm/{}\n\n\
+                     It works for 24 hours. If you didn't sign up, you can ignore this email.\n",
+                    self.app_url, token,
+                ),
+            })
+            .await
+    }
+
+    /// The link unlocking the account of `to` after too many failed logins.
+    pub async fn send_unlock(&self, to: &str, token: &str) -> Result<(), MailError> {
+        self.mailer
+            .send(Email {
+                to: to.to_string(),
+                subject: "Your account is locked".to_string(),
+                body: format!(
+                    "Someone failed to log into your account too many times, so we've locked \
+                     it. To unlock it, open this link:\n\n{}/unlock/{}\n\n\
+                     It works for 24 hours. If it wasn't you, consider changing your password.\n",
+                    self.app_url, token,
+                ),
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_mailer() {
+        let dir = std::env::temp_dir().join(format!("mailer-test-{}", crate::tokens::new_token()));
+        let outbox = Outbox::new(
+            Arc::new(FileMailer::new(&dir, "App <noreply@localhost>")),
+            "http://localhost:8000/",
+        );
+
+        outbox
+            .send_confirmation("john@example.com", "abc123")
+            .await
+            .unwrap();
+
+        let files = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        assert!(files[0]
+            .to_str()
+            .unwrap()
+            .ends_with("-john_example_com.eml"));
+        let contents = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(contents.starts_with("From: App <noreply@localhost>\r\nTo: john@example.com\r\n"));
+        assert!(contents.contains("http://localhost:8000/confirm/abc123\r\n"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,61 +1,88 @@
-use argon2::{self, Config};
-use async_redis_session::RedisSessionStore;
 use axum::{
     async_trait,
     error_handling::HandleErrorLayer,
-    extract::{FromRef, FromRequestParts, State},
+    extract::{ConnectInfo, FromRef, FromRequestParts, Path, State},
     http::{request::Parts, StatusCode},
-    response::{IntoResponse, Redirect},
-    routing::{get, get_service},
-    Router,
+    middleware,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, get_service, post},
+    Form, Router,
 };
 use axum_sessions::{
+    async_session,
     extractors::{ReadableSession, WritableSession},
-    SessionLayer,
+    PersistencePolicy, SessionLayer,
 };
 use bb8::{Pool, PooledConnection};
 use bb8_postgres::PostgresConnectionManager;
 use rand::Rng;
-use serde::Deserialize;
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::signal;
-use tokio_postgres::NoTls;
+use tokio_postgres::{error::SqlState, NoTls};
 use tower::{BoxError, ServiceBuilder};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use validator::Validate;
 
-use axum_http_auth::config::AppConfig;
+use axum_http_auth::config::{AppConfig, SessionStoreKind};
+use axum_http_auth::csrf::{self, CsrfToken};
+use axum_http_auth::mailer::{FileMailer, Outbox};
+use axum_http_auth::password::{self, Verification};
+use axum_http_auth::sessions::{
+    self, MemorySessionStore, PostgresSessionStore, RedisSessionStore, SessionBackend, Sessions,
+};
+use axum_http_auth::throttle::{
+    LoginThrottle, MemoryAttemptStore, PostgresAttemptStore, RedisAttemptStore, Throttled,
+};
+use axum_http_auth::tokens;
+use axum_http_auth::totp::{self, TotpError};
 use axum_http_auth::views::*;
 
 // Types /////////////////////////////
 
 type ConnectionPool = Pool<PostgresConnectionManager<NoTls>>;
 
+// Constants /////////////////////////
+
+const LOCKED_MESSAGE: &str = "Too many failed attempts, so this account is locked. \
+                              We've sent a link to unlock it to its email address.";
+
 // Structs //////////////////////////
 
-struct DatabaseConnection(PooledConnection<'static, PostgresConnectionManager<NoTls>>);
+#[derive(Clone)]
+struct AppState {
+    pool: ConnectionPool,
+    sessions: Sessions,
+    outbox: Outbox,
+    throttle: LoginThrottle,
+}
 
-#[derive(Debug, Deserialize, Validate)]
-pub struct CreateSignupInput {
-    // #[validate(email)]
-    pub email: String,
-    // #[validate(must_match = "confirm_password")]
-    // #[validate(length(min = 6))]
-    pub password: String,
-    // #[validate(must_match(other = "confirm_password"))]
-    pub confirm_password: String,
+impl FromRef<AppState> for ConnectionPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
 }
 
-#[derive(Debug, Deserialize, Validate)]
-pub struct CreateLoginInput {
-    #[validate(email)]
-    pub email: String,
-    #[validate(length(min = 6))]
-    pub password: String,
+impl FromRef<AppState> for Sessions {
+    fn from_ref(state: &AppState) -> Self {
+        state.sessions.clone()
+    }
 }
 
+impl FromRef<AppState> for Outbox {
+    fn from_ref(state: &AppState) -> Self {
+        state.outbox.clone()
+    }
+}
+
+impl FromRef<AppState> for LoginThrottle {
+    fn from_ref(state: &AppState) -> Self {
+        state.throttle.clone()
+    }
+}
+
+struct DatabaseConnection(PooledConnection<'static, PostgresConnectionManager<NoTls>>);
+
 // Traits ///////////////////////////////////////////
 
 #[async_trait]
@@ -77,8 +104,6 @@ where
 
 // Main /////////////////////////////
 
-const SALT: &str = "salt_goes_here";
-
 #[tokio::main]
 async fn main() {
     match envy::from_env::<AppConfig>() {
@@ -95,15 +120,19 @@ async fn main() {
             tracing::debug!("POSTGRES_USER: {:#?}", config.postgres_user);
             tracing::debug!("REDIS_HOST: {:#?}", config.redis_host);
             tracing::debug!("REDIS_PORT: {:#?}", config.redis_port);
-
-            let store = RedisSessionStore::new(format!(
-                "redis://{}:{}/",
-                config.redis_host, config.redis_port,
-            ))
-            .unwrap();
-            // let store = MemoryStore::new();
-            let secret = rand::thread_rng().gen::<[u8; 128]>();
-            let session_layer = SessionLayer::new(store, &secret);
+            tracing::debug!("SESSION_STORE: {:#?}", config.session_store);
+            tracing::debug!("SESSION_IDLE_TIMEOUT: {:#?}", config.session_idle_timeout);
+            tracing::debug!(
+                "SESSION_ABSOLUTE_TIMEOUT: {:#?}",
+                config.session_absolute_timeout
+            );
+            tracing::debug!("APP_URL: {:#?}", config.app_url);
+            tracing::debug!("MAIL_DIR: {:#?}", config.mail_dir);
+            tracing::debug!("MAIL_FROM: {:#?}", config.mail_from);
+            tracing::debug!(
+                "LOGIN_LOCKOUT_THRESHOLD: {:#?}",
+                config.login_lockout_threshold
+            );
 
             let connection_string = format!(
                 "host={} port={} user={} password={} dbname={} connect_timeout=10",
@@ -118,15 +147,90 @@ async fn main() {
 
             let pool = Pool::builder().max_size(30).build(manager).await.unwrap();
 
+            let redis_url = format!("redis://{}:{}/", config.redis_host, config.redis_port);
+            // Failed logins are counted where sessions are kept.
+            let (store, throttle) = match config.session_store {
+                SessionStoreKind::Memory => (
+                    SessionBackend::Memory(MemorySessionStore::new()),
+                    LoginThrottle::new(
+                        Arc::new(MemoryAttemptStore::new()),
+                        config.login_lockout_threshold,
+                    ),
+                ),
+                SessionStoreKind::Postgres => (
+                    SessionBackend::Postgres(PostgresSessionStore::new(pool.clone())),
+                    LoginThrottle::new(
+                        Arc::new(PostgresAttemptStore::new(pool.clone())),
+                        config.login_lockout_threshold,
+                    ),
+                ),
+                SessionStoreKind::Redis => (
+                    SessionBackend::Redis(
+                        RedisSessionStore::new(&redis_url).expect("Invalid Redis URL."),
+                    ),
+                    LoginThrottle::new(
+                        Arc::new(RedisAttemptStore::new(&redis_url).expect("Invalid Redis URL.")),
+                        config.login_lockout_threshold,
+                    ),
+                ),
+            };
+            let sessions = Sessions {
+                store,
+                idle_timeout: Duration::from_secs(config.session_idle_timeout),
+                absolute_timeout: Duration::from_secs(config.session_absolute_timeout),
+            };
+            tokio::spawn(sessions.clone().cleanup_every(Duration::from_secs(60 * 60)));
+
+            let mailer = FileMailer::new(&config.mail_dir, &config.mail_from);
+            let outbox = Outbox::new(Arc::new(mailer), &config.app_url);
+
+            let secret = rand::thread_rng().gen::<[u8; 128]>();
+            // Requests that don't put anything into their session, like those of visitors
+            // reading pages without forms, don't get one stored.
+            let session_layer = SessionLayer::new(sessions.store.clone(), &secret)
+                .with_session_ttl(Some(sessions.absolute_timeout))
+                .with_persistence_policy(PersistencePolicy::ChangedOnly);
+
             let app = Router::new()
                 .route("/", get(handle_root))
                 .route("/signup", get(handle_signup).post(handle_create_signup))
                 .route("/login", get(handle_login).post(handle_create_login))
-                .route("/logout", get(handle_logout))
+                .route(
+                    "/login/2fa",
+                    get(handle_login_2fa).post(handle_create_login_2fa),
+                )
+                .route("/logout", post(handle_logout))
+                .route("/confirm/:token", get(handle_confirm))
+                .route("/unlock/:token", get(handle_unlock))
                 .route("/account", get(handle_account_protected))
+                .route(
+                    "/account/2fa",
+                    get(handle_account_2fa).post(handle_create_account_2fa),
+                )
+                .route("/account/2fa/disable", post(handle_disable_account_2fa))
+                .route(
+                    "/account/deactivate",
+                    get(handle_deactivate).post(handle_create_deactivate),
+                )
+                .route(
+                    "/account/delete",
+                    get(handle_delete).post(handle_create_delete),
+                )
+                .route("/account/sessions", get(handle_account_sessions))
+                .route("/account/sessions/revoke", post(handle_revoke_session))
+                .route(
+                    "/account/sessions/revoke-others",
+                    post(handle_revoke_other_sessions),
+                )
+                // Only pages need a session, not the assets added below.
+                .layer(middleware::from_fn(csrf::protect))
+                .layer(middleware::from_fn_with_state(
+                    sessions.clone(),
+                    sessions::enforce_timeouts,
+                ))
+                .layer(session_layer)
                 .nest_service("/public", get_service(ServeDir::new("public")))
                 // Add middleware to all routes
-                .layer(session_layer)
                 .layer(
                     ServiceBuilder::new()
                         .layer(HandleErrorLayer::new(|error: BoxError| async move {
@@ -143,19 +247,24 @@ async fn main() {
                         .layer(TraceLayer::new_for_http())
                         .into_inner(),
                 )
-                .with_state(pool);
+                .with_state(AppState {
+                    pool,
+                    sessions,
+                    outbox,
+                    throttle,
+                });
 
             let addr = SocketAddr::from(([0, 0, 0, 0], 8000));
             tracing::debug!("listening on http://{}", addr);
             if config.environment == "production" {
                 axum::Server::bind(&addr)
-                    .serve(app.into_make_service())
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                     .with_graceful_shutdown(shutdown_signal())
                     .await
                     .unwrap();
             } else {
                 axum::Server::bind(&addr)
-                    .serve(app.into_make_service())
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                     .await
                     .unwrap();
             }
@@ -166,94 +275,302 @@ async fn main() {
 
 // Route Handlers ////////////////////////////////
 
-async fn handle_root() -> impl IntoResponse {
+async fn handle_root(CsrfToken(csrf_token): CsrfToken) -> impl IntoResponse {
     let template = IndexTemplate {
         title: "App".to_string(),
+        csrf_token,
     };
     HtmlTemplate(template)
 }
 
-async fn handle_login() -> impl IntoResponse {
+async fn handle_login(CsrfToken(csrf_token): CsrfToken) -> impl IntoResponse {
     let template = LoginTemplate {
         title: "App - Login".to_string(),
-        errors: validator::ValidationErrors::new(),
+        csrf_token,
+        email: String::new(),
+        errors: FieldErrors::new(),
+        lockout: None,
     };
     HtmlTemplate(template)
 }
 
-async fn handle_signup() -> impl IntoResponse {
+async fn handle_signup(CsrfToken(csrf_token): CsrfToken) -> impl IntoResponse {
     let template = SignupTemplate {
         title: "App - Signup".to_string(),
-        errors: validator::ValidationErrors::new(),
+        csrf_token,
+        email: String::new(),
+        errors: FieldErrors::new(),
     };
     HtmlTemplate(template)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_create_login(
     State(pool): State<ConnectionPool>,
+    State(sessions): State<Sessions>,
+    State(outbox): State<Outbox>,
+    State(throttle): State<LoginThrottle>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     mut session: WritableSession,
-    ValidatedLoginForm(input): ValidatedLoginForm<CreateLoginInput>,
-) -> impl IntoResponse {
-    let config = Config::default();
-    let hash = argon2::hash_encoded(input.password.as_bytes(), SALT.as_bytes(), &config).unwrap();
-    let conn = pool.get().await.map_err(internal_error).unwrap();
-    let query = conn
-        .query_one(
-            "select * FROM accounts where active = true AND email = $1 AND password = $2 LIMIT 1",
-            &[&input.email, &hash],
-        )
-        .await;
-    match query {
-        Ok(_) => {
-            session
-                .insert("email", input.email)
-                .expect("Session could not be created.");
-            Redirect::to("/account").into_response()
+    CsrfToken(csrf_token): CsrfToken,
+    ValidatedLoginForm(input): ValidatedLoginForm,
+) -> Result<Response, (StatusCode, String)> {
+    let ip = addr.ip().to_string();
+    match throttle
+        .check(&input.email, &ip)
+        .await
+        .map_err(internal_error)?
+    {
+        Throttled::No => (),
+        Throttled::Wait(wait) => {
+            return Ok(lockout_response(
+                csrf_token,
+                input.email,
+                wait_message(wait),
+            ))
         }
-        Err(_) => {
-            let p = validator::ValidationError::new("password");
-            let mut vs = validator::ValidationErrors::new();
-            vs.add("password", p);
+        Throttled::Locked => {
+            return Ok(lockout_response(
+                csrf_token,
+                input.email,
+                LOCKED_MESSAGE.to_string(),
+            ))
+        }
+    }
+
+    let conn = pool.get().await.map_err(internal_error)?;
+    let row = conn
+        .query_opt(
+            "select password, active, locked_at IS NOT NULL AS locked, totp_secret
+             FROM accounts where email = $1",
+            &[&input.email],
+        )
+        .await
+        .map_err(internal_error)?;
+    let (stored, active, locked, totp_secret) = match row {
+        Some(row) => (
+            Some(row.get::<_, String>("password")),
+            row.get::<_, bool>("active"),
+            row.get::<_, bool>("locked"),
+            row.get::<_, Option<String>>("totp_secret"),
+        ),
+        None => (None, false, false, None),
+    };
+
+    if locked {
+        // Even the right password doesn't help until the account is unlocked.
+        send_unlock(&conn, &outbox, &input.email).await?;
+        return Ok(lockout_response(
+            csrf_token,
+            input.email,
+            LOCKED_MESSAGE.to_string(),
+        ));
+    }
+
+    match password::spawn_verify(stored, input.password)
+        .await
+        .map_err(internal_error)?
+    {
+        Verification::Invalid => {
+            if record_login_failure(&conn, &throttle, &outbox, &input.email, &ip).await? {
+                return Ok(lockout_response(
+                    csrf_token,
+                    input.email,
+                    LOCKED_MESSAGE.to_string(),
+                ));
+            }
+
+            // Whether the email has an account is nobody's business.
+            let mut errors = FieldErrors::new();
+            errors.add("password", "Incorrect email or password.");
             let template = LoginTemplate {
                 title: "App - Login|Error".to_string(),
-                errors: vs,
+                csrf_token,
+                email: input.email,
+                errors,
+                lockout: None,
             };
-            HtmlTemplate(template).into_response()
+            return Ok(HtmlTemplate(template).into_response());
+        }
+        Verification::Valid => (),
+        Verification::Rehashed(hash) => {
+            conn.execute(
+                "UPDATE accounts SET password = $2 WHERE email = $1",
+                &[&input.email, &hash],
+            )
+            .await
+            .map_err(internal_error)?;
         }
     }
+
+    if !active {
+        // Unconfirmed or deactivated, either way confirming the email address activates it.
+        send_confirmation(&conn, &outbox, &input.email).await?;
+        let mut errors = FieldErrors::new();
+        errors.add(
+            "email",
+            "Confirm your email address first. We've sent you a new link.",
+        );
+        let template = LoginTemplate {
+            title: "App - Login|Error".to_string(),
+            csrf_token,
+            email: input.email,
+            errors,
+            lockout: None,
+        };
+        return Ok(HtmlTemplate(template).into_response());
+    }
+
+    sessions.rotate(&mut session).await.map_err(session_error)?;
+
+    if totp_secret.is_some() {
+        // Not logged in until the second factor checks out too.
+        session.remove("email");
+        session
+            .insert("pending_2fa_email", input.email)
+            .expect("Session could not be created.");
+        return Ok(Redirect::to("/login/2fa").into_response());
+    }
+
+    throttle.reset(&input.email).await.map_err(internal_error)?;
+    session
+        .insert("email", input.email)
+        .expect("Session could not be created.");
+    Ok(Redirect::to("/account").into_response())
 }
 
-async fn handle_create_signup(
+async fn handle_login_2fa(
+    session: ReadableSession,
+    CsrfToken(csrf_token): CsrfToken,
+) -> impl IntoResponse {
+    if session.get::<String>("pending_2fa_email").is_none() {
+        return Redirect::to("/login").into_response();
+    }
+
+    let template = TwoFactorLoginTemplate {
+        title: "App - Login".to_string(),
+        csrf_token,
+        errors: FieldErrors::new(),
+    };
+    HtmlTemplate(template).into_response()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_create_login_2fa(
     State(pool): State<ConnectionPool>,
+    State(sessions): State<Sessions>,
+    State(outbox): State<Outbox>,
+    State(throttle): State<LoginThrottle>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     mut session: WritableSession,
-    ValidatedSignupForm(input): ValidatedSignupForm<CreateSignupInput>,
-) -> impl IntoResponse {
-    let config = Config::default();
-    let hash = argon2::hash_encoded(input.password.as_bytes(), SALT.as_bytes(), &config).unwrap();
-    let conn = pool.get().await.map_err(internal_error).unwrap();
+    CsrfToken(csrf_token): CsrfToken,
+    Form(input): Form<TwoFactorCodeInput>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(email) = session.get::<String>("pending_2fa_email") else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    // Codes are counted like passwords, or they would be easy to guess.
+    let ip = addr.ip().to_string();
+    match throttle.check(&email, &ip).await.map_err(internal_error)? {
+        Throttled::No => (),
+        Throttled::Wait(wait) => {
+            let mut errors = FieldErrors::new();
+            errors.add("code", &wait_message(wait));
+            let template = TwoFactorLoginTemplate {
+                title: "App - Login|Error".to_string(),
+                csrf_token,
+                errors,
+            };
+            return Ok((StatusCode::TOO_MANY_REQUESTS, HtmlTemplate(template)).into_response());
+        }
+        Throttled::Locked => {
+            session.remove("pending_2fa_email");
+            return Ok(lockout_response(
+                csrf_token,
+                email,
+                LOCKED_MESSAGE.to_string(),
+            ));
+        }
+    }
+
+    let conn = pool.get().await.map_err(internal_error)?;
+    let Some(secret) = totp_secret(&conn, &email).await? else {
+        // 2FA was turned off, or the account deactivated, in the meantime.
+        session.remove("pending_2fa_email");
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    if !check_second_factor(&conn, &email, &secret, &input.code).await? {
+        if record_login_failure(&conn, &throttle, &outbox, &email, &ip).await? {
+            session.remove("pending_2fa_email");
+            return Ok(lockout_response(
+                csrf_token,
+                email,
+                LOCKED_MESSAGE.to_string(),
+            ));
+        }
+
+        let mut errors = FieldErrors::new();
+        errors.add("code", "That code didn't work. Try again.");
+        let template = TwoFactorLoginTemplate {
+            title: "App - Login|Error".to_string(),
+            csrf_token,
+            errors,
+        };
+        return Ok(HtmlTemplate(template).into_response());
+    }
+
+    throttle.reset(&email).await.map_err(internal_error)?;
+    sessions.rotate(&mut session).await.map_err(session_error)?;
+    session.remove("pending_2fa_email");
+    session
+        .insert("email", email)
+        .expect("Session could not be created.");
+    Ok(Redirect::to("/account").into_response())
+}
+
+async fn handle_create_signup(
+    State(pool): State<ConnectionPool>,
+    State(outbox): State<Outbox>,
+    CsrfToken(csrf_token): CsrfToken,
+    ValidatedSignupForm(input): ValidatedSignupForm,
+) -> Result<Response, (StatusCode, String)> {
+    let hash = password::spawn_hash(input.password)
+        .await
+        .map_err(internal_error)?;
+    let conn = pool.get().await.map_err(internal_error)?;
     let query = conn
         .execute(
-            "INSERT into accounts (email, password) VALUES($1,$2)",
+            "INSERT into accounts (email, password, active) VALUES($1,$2,false)",
             &[&input.email, &hash],
         )
         .await;
     match query {
         Ok(_) => {
-            session
-                .insert("email", input.email)
-                .expect("Session could not be created.");
-            Redirect::to("/account").into_response()
+            send_confirmation(&conn, &outbox, &input.email).await?;
+            let template = NoticeTemplate {
+                title: "App - Signup".to_string(),
+                message: format!(
+                    "Almost done! Open the link we've sent to {} to activate your account.",
+                    input.email
+                ),
+                csrf_token,
+            };
+            Ok(HtmlTemplate(template).into_response())
         }
-        Err(_) => {
-            let p = validator::ValidationError::new("password");
-            let mut vs = validator::ValidationErrors::new();
-            vs.add("password", p);
+        Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+            let mut errors = FieldErrors::new();
+            errors.add("email", "An account with this email already exists.");
             let template = SignupTemplate {
                 title: "App - Signup|Error".to_string(),
-                errors: vs,
+                csrf_token,
+                email: input.email,
+                errors,
             };
-            HtmlTemplate(template).into_response()
+            Ok(HtmlTemplate(template).into_response())
         }
+        Err(e) => Err(internal_error(e)),
     }
 }
 
@@ -262,26 +579,746 @@ async fn handle_logout(mut session: WritableSession) -> impl IntoResponse {
     Redirect::to("/")
 }
 
-async fn handle_account_protected(session: ReadableSession) -> impl IntoResponse {
+async fn handle_confirm(
+    State(pool): State<ConnectionPool>,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(token): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let conn = pool.get().await.map_err(internal_error)?;
+    let Some(email) = use_account_token(&conn, &token, tokens::CONFIRM).await? else {
+        let template = ErrorTemplate {
+            title: "App - Confirm Email|Error".to_string(),
+            message: "This link is invalid or has expired. Log in to get a new one.".to_string(),
+            csrf_token,
+        };
+        return Ok((StatusCode::NOT_FOUND, HtmlTemplate(template)).into_response());
+    };
+
+    conn.execute(
+        "UPDATE accounts SET active = true WHERE email = $1",
+        &[&email],
+    )
+    .await
+    .map_err(internal_error)?;
+
+    let template = NoticeTemplate {
+        title: "App - Confirm Email".to_string(),
+        message: "Your email address is confirmed. You can log in now.".to_string(),
+        csrf_token,
+    };
+    Ok(HtmlTemplate(template).into_response())
+}
+
+async fn handle_unlock(
+    State(pool): State<ConnectionPool>,
+    State(throttle): State<LoginThrottle>,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(token): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let conn = pool.get().await.map_err(internal_error)?;
+    let Some(email) = use_account_token(&conn, &token, tokens::UNLOCK).await? else {
+        let template = ErrorTemplate {
+            title: "App - Unlock Account|Error".to_string(),
+            message: "This link is invalid or has expired. Try to log in to get a new one."
+                .to_string(),
+            csrf_token,
+        };
+        return Ok((StatusCode::NOT_FOUND, HtmlTemplate(template)).into_response());
+    };
+
+    conn.execute(
+        "UPDATE accounts SET locked_at = NULL WHERE email = $1",
+        &[&email],
+    )
+    .await
+    .map_err(internal_error)?;
+    throttle.reset(&email).await.map_err(internal_error)?;
+
+    let template = NoticeTemplate {
+        title: "App - Unlock Account".to_string(),
+        message: "Your account is unlocked. You can log in now.".to_string(),
+        csrf_token,
+    };
+    Ok(HtmlTemplate(template).into_response())
+}
+
+async fn handle_account_protected(
+    State(pool): State<ConnectionPool>,
+    session: ReadableSession,
+    CsrfToken(csrf_token): CsrfToken,
+) -> Result<Response, (StatusCode, String)> {
     let email = session
         .get::<String>("email")
         .map_or(String::from(""), |s| s);
 
-    if email != "" {
+    if !email.is_empty() {
+        let conn = pool.get().await.map_err(internal_error)?;
         let authed_template = AccountTemplate {
             title: "App - Account".to_string(),
-            email: format!("{}", email),
+            csrf_token,
+            totp_enabled: totp_secret(&conn, &email).await?.is_some(),
+            email,
         };
-        HtmlTemplate(authed_template).into_response()
+        Ok(HtmlTemplate(authed_template).into_response())
     } else {
-        Redirect::to("/login").into_response()
+        Ok(Redirect::to("/login").into_response())
     }
 }
 
+async fn handle_account_2fa(
+    State(pool): State<ConnectionPool>,
+    mut session: WritableSession,
+    CsrfToken(csrf_token): CsrfToken,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(email) = session.get::<String>("email") else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let conn = pool.get().await.map_err(internal_error)?;
+    let pending_secret = if totp_secret(&conn, &email).await?.is_some() {
+        None
+    } else {
+        // Kept until confirmed, so that reloading the page doesn't invalidate a scanned code.
+        let secret = session
+            .get::<String>("pending_totp_secret")
+            .unwrap_or_else(totp::new_secret);
+        session
+            .insert("pending_totp_secret", &secret)
+            .expect("Session could not be created.");
+        Some(secret)
+    };
+
+    let template = two_factor_template(csrf_token, &email, pending_secret, FieldErrors::new())
+        .map_err(internal_error)?;
+    Ok(HtmlTemplate(template).into_response())
+}
+
+async fn handle_create_account_2fa(
+    State(pool): State<ConnectionPool>,
+    mut session: WritableSession,
+    CsrfToken(csrf_token): CsrfToken,
+    Form(input): Form<TwoFactorCodeInput>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(email) = session.get::<String>("email") else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let Some(secret) = session.get::<String>("pending_totp_secret") else {
+        return Ok(Redirect::to("/account/2fa").into_response());
+    };
+
+    let Some(step) =
+        totp::check_code(&secret, &email, &input.code, None).map_err(internal_error)?
+    else {
+        let mut errors = FieldErrors::new();
+        errors.add("code", "That code didn't work. Try again.");
+        let template = two_factor_template(csrf_token, &email, Some(secret), errors)
+            .map_err(internal_error)?;
+        return Ok(HtmlTemplate(template).into_response());
+    };
+
+    let codes = totp::new_recovery_codes();
+    let mut hashes = Vec::with_capacity(codes.len());
+    for code in &codes {
+        let code = totp::parse_recovery_code(code).unwrap();
+        hashes.push(password::spawn_hash(code).await.map_err(internal_error)?);
+    }
+
+    let mut conn = pool.get().await.map_err(internal_error)?;
+    let transaction = conn.transaction().await.map_err(internal_error)?;
+    transaction
+        .execute(
+            "UPDATE accounts SET totp_secret = $2, totp_last_step = $3 WHERE email = $1",
+            &[&email, &secret, &step],
+        )
+        .await
+        .map_err(internal_error)?;
+    transaction
+        .execute("DELETE FROM recovery_codes WHERE email = $1", &[&email])
+        .await
+        .map_err(internal_error)?;
+    for hash in &hashes {
+        transaction
+            .execute(
+                "INSERT into recovery_codes (email, code_hash) VALUES($1,$2)",
+                &[&email, hash],
+            )
+            .await
+            .map_err(internal_error)?;
+    }
+    transaction.commit().await.map_err(internal_error)?;
+
+    session.remove("pending_totp_secret");
+    let template = RecoveryCodesTemplate {
+        title: "App - Recovery Codes".to_string(),
+        csrf_token,
+        codes,
+    };
+    Ok(HtmlTemplate(template).into_response())
+}
+
+async fn handle_disable_account_2fa(
+    State(pool): State<ConnectionPool>,
+    State(outbox): State<Outbox>,
+    State(throttle): State<LoginThrottle>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut session: WritableSession,
+    CsrfToken(csrf_token): CsrfToken,
+    Form(input): Form<TwoFactorCodeInput>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(email) = session.get::<String>("email") else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let mut conn = pool.get().await.map_err(internal_error)?;
+    let Some(secret) = totp_secret(&conn, &email).await? else {
+        return Ok(Redirect::to("/account").into_response());
+    };
+
+    let ip = addr.ip().to_string();
+    let check = check_second_factor(&conn, &email, &secret, &input.code);
+    let refused = match reenter(&conn, &throttle, &outbox, &email, &ip, check).await? {
+        Reentry::Confirmed => None,
+        Reentry::Wrong => Some((
+            StatusCode::OK,
+            "That code didn't work. Try again.".to_string(),
+        )),
+        Reentry::Wait(wait) => Some((StatusCode::TOO_MANY_REQUESTS, wait_message(wait))),
+        Reentry::Locked => {
+            session.destroy();
+            return Ok(lockout_response(
+                csrf_token,
+                email,
+                LOCKED_MESSAGE.to_string(),
+            ));
+        }
+    };
+    if let Some((status, message)) = refused {
+        let mut errors = FieldErrors::new();
+        errors.add("code", &message);
+        let template =
+            two_factor_template(csrf_token, &email, None, errors).map_err(internal_error)?;
+        return Ok((status, HtmlTemplate(template)).into_response());
+    }
+
+    let transaction = conn.transaction().await.map_err(internal_error)?;
+    transaction
+        .execute(
+            "UPDATE accounts SET totp_secret = NULL, totp_last_step = NULL WHERE email = $1",
+            &[&email],
+        )
+        .await
+        .map_err(internal_error)?;
+    transaction
+        .execute("DELETE FROM recovery_codes WHERE email = $1", &[&email])
+        .await
+        .map_err(internal_error)?;
+    transaction.commit().await.map_err(internal_error)?;
+
+    Ok(Redirect::to("/account").into_response())
+}
+
 // async fn handle_error(_err: io::Error) -> impl IntoResponse {
 //     (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong...")
 // }
 
+async fn handle_deactivate(
+    session: ReadableSession,
+    CsrfToken(csrf_token): CsrfToken,
+) -> impl IntoResponse {
+    if session.get::<String>("email").is_none() {
+        return Redirect::to("/login").into_response();
+    }
+
+    let template = DeactivateTemplate {
+        title: "App - Deactivate Account".to_string(),
+        csrf_token,
+        errors: FieldErrors::new(),
+    };
+    HtmlTemplate(template).into_response()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_create_deactivate(
+    State(pool): State<ConnectionPool>,
+    State(sessions): State<Sessions>,
+    State(outbox): State<Outbox>,
+    State(throttle): State<LoginThrottle>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut session: WritableSession,
+    CsrfToken(csrf_token): CsrfToken,
+    Form(input): Form<PasswordConfirmationInput>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(email) = session.get::<String>("email") else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let conn = pool.get().await.map_err(internal_error)?;
+    let ip = addr.ip().to_string();
+    let check = check_password(&conn, &email, &input.password);
+    let refused = match reenter(&conn, &throttle, &outbox, &email, &ip, check).await? {
+        Reentry::Confirmed => None,
+        Reentry::Wrong => Some((StatusCode::OK, "Incorrect password.".to_string())),
+        Reentry::Wait(wait) => Some((StatusCode::TOO_MANY_REQUESTS, wait_message(wait))),
+        Reentry::Locked => {
+            session.destroy();
+            return Ok(lockout_response(
+                csrf_token,
+                email,
+                LOCKED_MESSAGE.to_string(),
+            ));
+        }
+    };
+    if let Some((status, message)) = refused {
+        let mut errors = FieldErrors::new();
+        errors.add("password", &message);
+        let template = DeactivateTemplate {
+            title: "App - Deactivate Account|Error".to_string(),
+            csrf_token,
+            errors,
+        };
+        return Ok((status, HtmlTemplate(template)).into_response());
+    }
+
+    conn.execute(
+        "UPDATE accounts SET active = false WHERE email = $1",
+        &[&email],
+    )
+    .await
+    .map_err(internal_error)?;
+    sessions
+        .revoke_all(&email, Some(session.id()))
+        .await
+        .map_err(session_error)?;
+    session.destroy();
+
+    let template = NoticeTemplate {
+        title: "App - Deactivate Account".to_string(),
+        message: "Your account is deactivated. To reactivate it, log in and confirm your email \
+                  address."
+            .to_string(),
+        csrf_token,
+    };
+    Ok(HtmlTemplate(template).into_response())
+}
+
+async fn handle_delete(
+    session: ReadableSession,
+    CsrfToken(csrf_token): CsrfToken,
+) -> impl IntoResponse {
+    if session.get::<String>("email").is_none() {
+        return Redirect::to("/login").into_response();
+    }
+
+    let template = DeleteAccountTemplate {
+        title: "App - Delete Account".to_string(),
+        csrf_token,
+        errors: FieldErrors::new(),
+    };
+    HtmlTemplate(template).into_response()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_create_delete(
+    State(pool): State<ConnectionPool>,
+    State(sessions): State<Sessions>,
+    State(outbox): State<Outbox>,
+    State(throttle): State<LoginThrottle>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut session: WritableSession,
+    CsrfToken(csrf_token): CsrfToken,
+    Form(input): Form<PasswordConfirmationInput>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(email) = session.get::<String>("email") else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let conn = pool.get().await.map_err(internal_error)?;
+    let ip = addr.ip().to_string();
+    let check = check_password(&conn, &email, &input.password);
+    let refused = match reenter(&conn, &throttle, &outbox, &email, &ip, check).await? {
+        Reentry::Confirmed => None,
+        Reentry::Wrong => Some((StatusCode::OK, "Incorrect password.".to_string())),
+        Reentry::Wait(wait) => Some((StatusCode::TOO_MANY_REQUESTS, wait_message(wait))),
+        Reentry::Locked => {
+            session.destroy();
+            return Ok(lockout_response(
+                csrf_token,
+                email,
+                LOCKED_MESSAGE.to_string(),
+            ));
+        }
+    };
+    if let Some((status, message)) = refused {
+        let mut errors = FieldErrors::new();
+        errors.add("password", &message);
+        let template = DeleteAccountTemplate {
+            title: "App - Delete Account|Error".to_string(),
+            csrf_token,
+            errors,
+        };
+        return Ok((status, HtmlTemplate(template)).into_response());
+    }
+
+    sessions
+        .revoke_all(&email, Some(session.id()))
+        .await
+        .map_err(session_error)?;
+    // Recovery codes, tokens and sessions in Postgres go with it.
+    conn.execute("DELETE FROM accounts WHERE email = $1", &[&email])
+        .await
+        .map_err(internal_error)?;
+    session.destroy();
+
+    let template = NoticeTemplate {
+        title: "App - Delete Account".to_string(),
+        message: "Your account is deleted.".to_string(),
+        csrf_token,
+    };
+    Ok(HtmlTemplate(template).into_response())
+}
+
+async fn handle_account_sessions(
+    State(sessions): State<Sessions>,
+    session: ReadableSession,
+    CsrfToken(csrf_token): CsrfToken,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(email) = session.get::<String>("email") else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let template = SessionsTemplate {
+        title: "App - Active Sessions".to_string(),
+        csrf_token,
+        sessions: sessions
+            .list(&email, &session)
+            .await
+            .map_err(session_error)?,
+    };
+    Ok(HtmlTemplate(template).into_response())
+}
+
+async fn handle_revoke_session(
+    State(sessions): State<Sessions>,
+    session: ReadableSession,
+    Form(input): Form<RevokeSessionInput>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(email) = session.get::<String>("email") else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    // Logging out this session is what `/logout` is for.
+    if input.id != session.id() {
+        sessions
+            .revoke(&email, &input.id)
+            .await
+            .map_err(session_error)?;
+    }
+    Ok(Redirect::to("/account/sessions").into_response())
+}
+
+async fn handle_revoke_other_sessions(
+    State(sessions): State<Sessions>,
+    session: ReadableSession,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(email) = session.get::<String>("email") else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    sessions
+        .revoke_all(&email, Some(session.id()))
+        .await
+        .map_err(session_error)?;
+    Ok(Redirect::to("/account/sessions").into_response())
+}
+
+// Accounts ////////////////////////////////////////
+
+/// Whether `password` is the one of the active account `email`, for pages that ask for it again.
+async fn check_password(
+    conn: &tokio_postgres::Client,
+    email: &str,
+    password: &str,
+) -> Result<bool, (StatusCode, String)> {
+    let row = conn
+        .query_opt(
+            "select password FROM accounts where active = true AND email = $1",
+            &[&email],
+        )
+        .await
+        .map_err(internal_error)?;
+    let stored = row.map(|row| row.get::<_, String>("password"));
+
+    let verification = password::spawn_verify(stored, password.to_string())
+        .await
+        .map_err(internal_error)?;
+    Ok(verification != Verification::Invalid)
+}
+
+/// How entering the password or a 2FA code again went.
+enum Reentry {
+    Confirmed,
+    Wrong,
+    /// Too many failures, try again after this long.
+    Wait(Duration),
+    /// Too many failures, the account is locked now.
+    Locked,
+}
+
+/// Run `check`, which tells whether the password or 2FA code entered again on a page of a logged
+/// in session is right. These count towards the same limits as logins, so that a hijacked
+/// session is no way around them.
+async fn reenter(
+    conn: &tokio_postgres::Client,
+    throttle: &LoginThrottle,
+    outbox: &Outbox,
+    email: &str,
+    ip: &str,
+    check: impl std::future::Future<Output = Result<bool, (StatusCode, String)>>,
+) -> Result<Reentry, (StatusCode, String)> {
+    match throttle.check(email, ip).await.map_err(internal_error)? {
+        Throttled::No => (),
+        Throttled::Wait(wait) => return Ok(Reentry::Wait(wait)),
+        Throttled::Locked => return Ok(Reentry::Locked),
+    }
+
+    if check.await? {
+        throttle.reset(email).await.map_err(internal_error)?;
+        Ok(Reentry::Confirmed)
+    } else if record_login_failure(conn, throttle, outbox, email, ip).await? {
+        Ok(Reentry::Locked)
+    } else {
+        Ok(Reentry::Wrong)
+    }
+}
+
+/// Email `email` a link activating its account.
+async fn send_confirmation(
+    conn: &tokio_postgres::Client,
+    outbox: &Outbox,
+    email: &str,
+) -> Result<(), (StatusCode, String)> {
+    let token = create_account_token(conn, email, tokens::CONFIRM).await?;
+    outbox
+        .send_confirmation(email, &token)
+        .await
+        .map_err(internal_error)
+}
+
+/// Count a failed login into `email` from `ip`. Once there were too many, the account is locked
+/// and its owner emailed a link to unlock it, and this returns `true`. Emails without an account
+/// get the same answer, so that it doesn't tell which ones have one, but no email.
+async fn record_login_failure(
+    conn: &tokio_postgres::Client,
+    throttle: &LoginThrottle,
+    outbox: &Outbox,
+    email: &str,
+    ip: &str,
+) -> Result<bool, (StatusCode, String)> {
+    if !throttle
+        .record_failure(email, ip)
+        .await
+        .map_err(internal_error)?
+    {
+        return Ok(false);
+    }
+
+    let accounts = conn
+        .execute(
+            "UPDATE accounts SET locked_at = coalesce(locked_at, now()) WHERE email = $1",
+            &[&email],
+        )
+        .await
+        .map_err(internal_error)?;
+    if accounts == 1 {
+        send_unlock(conn, outbox, email).await?;
+    }
+    Ok(true)
+}
+
+/// Email `email` a link unlocking its account, unless it has one that still works.
+async fn send_unlock(
+    conn: &tokio_postgres::Client,
+    outbox: &Outbox,
+    email: &str,
+) -> Result<(), (StatusCode, String)> {
+    let pending = conn
+        .query_opt(
+            "select 1 FROM account_tokens
+             where email = $1 AND purpose = $2 AND expires_at > now()",
+            &[&email, &tokens::UNLOCK],
+        )
+        .await
+        .map_err(internal_error)?;
+    if pending.is_some() {
+        return Ok(());
+    }
+
+    let token = create_account_token(conn, email, tokens::UNLOCK).await?;
+    outbox
+        .send_unlock(email, &token)
+        .await
+        .map_err(internal_error)
+}
+
+/// The login page, saying why logging in doesn't work right now.
+fn lockout_response(csrf_token: String, email: String, lockout: String) -> Response {
+    let template = LoginTemplate {
+        title: "App - Login|Error".to_string(),
+        csrf_token,
+        email,
+        errors: FieldErrors::new(),
+        lockout: Some(lockout),
+    };
+    (StatusCode::TOO_MANY_REQUESTS, HtmlTemplate(template)).into_response()
+}
+
+fn wait_message(wait: Duration) -> String {
+    format!(
+        "Too many failed attempts. Try again in {} seconds.",
+        wait.as_secs().max(1)
+    )
+}
+
+/// Store a new token of `purpose` for `email`, which is valid for a day.
+async fn create_account_token(
+    conn: &tokio_postgres::Client,
+    email: &str,
+    purpose: &str,
+) -> Result<String, (StatusCode, String)> {
+    conn.execute("DELETE FROM account_tokens WHERE expires_at < now()", &[])
+        .await
+        .map_err(internal_error)?;
+
+    let token = tokens::new_token();
+    conn.execute(
+        "INSERT into account_tokens (token_hash, email, purpose, expires_at)
+         VALUES($1,$2,$3,now() + interval '1 day')",
+        &[&tokens::hash(&token), &email, &purpose],
+    )
+    .await
+    .map_err(internal_error)?;
+    Ok(token)
+}
+
+/// The email of the account `token` is for, if it's an unexpired token of `purpose`. Tokens
+/// only work once.
+async fn use_account_token(
+    conn: &tokio_postgres::Client,
+    token: &str,
+    purpose: &str,
+) -> Result<Option<String>, (StatusCode, String)> {
+    let row = conn
+        .query_opt(
+            "DELETE FROM account_tokens
+             WHERE token_hash = $1 AND purpose = $2 AND expires_at > now()
+             RETURNING email",
+            &[&tokens::hash(token), &purpose],
+        )
+        .await
+        .map_err(internal_error)?;
+    Ok(row.map(|row| row.get::<_, String>("email")))
+}
+
+// Two-Factor Authentication ////////////////////////
+
+/// The TOTP secret of an active account, if it has 2FA on.
+async fn totp_secret(
+    conn: &tokio_postgres::Client,
+    email: &str,
+) -> Result<Option<String>, (StatusCode, String)> {
+    let row = conn
+        .query_opt(
+            "select totp_secret FROM accounts where active = true AND email = $1",
+            &[&email],
+        )
+        .await
+        .map_err(internal_error)?;
+    Ok(row.and_then(|row| row.get::<_, Option<String>>("totp_secret")))
+}
+
+/// Whether `code` is the current TOTP code of `secret`, not used before, or one of the
+/// account's recovery codes. Either is used up by this.
+async fn check_second_factor(
+    conn: &tokio_postgres::Client,
+    email: &str,
+    secret: &str,
+    code: &str,
+) -> Result<bool, (StatusCode, String)> {
+    let last_step = conn
+        .query_one(
+            "select totp_last_step FROM accounts where email = $1",
+            &[&email],
+        )
+        .await
+        .map_err(internal_error)?
+        .get::<_, Option<i64>>("totp_last_step");
+    if let Some(step) = totp::check_code(secret, email, code, last_step).map_err(internal_error)? {
+        // Zero rows means a concurrent login used it, or a later one, first.
+        let updated = conn
+            .execute(
+                "UPDATE accounts SET totp_last_step = $2
+                WHERE email = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)",
+                &[&email, &step],
+            )
+            .await
+            .map_err(internal_error)?;
+        return Ok(updated == 1);
+    }
+
+    let Some(code) = totp::parse_recovery_code(code) else {
+        return Ok(false);
+    };
+    let rows = conn
+        .query(
+            "select code_hash FROM recovery_codes where email = $1",
+            &[&email],
+        )
+        .await
+        .map_err(internal_error)?;
+    for row in rows {
+        let hash = row.get::<_, String>("code_hash");
+        if password::spawn_verify(Some(hash.clone()), code.clone())
+            .await
+            .map_err(internal_error)?
+            == Verification::Invalid
+        {
+            continue;
+        }
+        // Zero rows means a concurrent login used it first.
+        let deleted = conn
+            .execute(
+                "DELETE FROM recovery_codes WHERE email = $1 AND code_hash = $2",
+                &[&email, &hash],
+            )
+            .await
+            .map_err(internal_error)?;
+        return Ok(deleted == 1);
+    }
+
+    Ok(false)
+}
+
+/// The 2FA settings page, which enrolls `pending_secret` unless 2FA is on already.
+fn two_factor_template(
+    csrf_token: String,
+    email: &str,
+    pending_secret: Option<String>,
+    errors: FieldErrors,
+) -> Result<TwoFactorTemplate, TotpError> {
+    let (enabled, qr_code, secret) = match pending_secret {
+        Some(secret) => (false, totp::qr_code_svg(&secret, email)?, secret),
+        None => (true, String::new(), String::new()),
+    };
+    Ok(TwoFactorTemplate {
+        title: "App - Two-Factor Authentication".to_string(),
+        csrf_token,
+        enabled,
+        qr_code,
+        secret,
+        errors,
+    })
+}
+
 // Utility Functions ////////////////////////////////
 
 /// for mapping any error into a `500 Internal Server Error` response.
@@ -292,6 +1329,12 @@ where
     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 }
 
+/// for mapping session store errors, which aren't `std::error::Error`s, into a
+/// `500 Internal Server Error` response.
+fn session_error(err: async_session::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
 // Graceful shutdown //////////////////////////////////
 
 async fn shutdown_signal() {
@@ -319,3 +1362,96 @@ async fn shutdown_signal() {
 
     tracing::debug!("signal received, starting graceful shutdown");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client of the database in `AppConfig`, with `db/schema.sql` loaded into a schema of
+    /// its own, which is dropped with `drop_schema`.
+    async fn test_client(schema: &str) -> tokio_postgres::Client {
+        let config = envy::from_env::<AppConfig>().unwrap();
+        let (client, connection) = tokio_postgres::connect(
+            &format!(
+                "host={} port={} user={} password={} dbname={}",
+                config.postgres_host,
+                config.postgres_port,
+                config.postgres_user,
+                config.postgres_password,
+                config.postgres_db,
+            ),
+            NoTls,
+        )
+        .await
+        .unwrap();
+        tokio::spawn(connection);
+
+        client
+            .batch_execute(&format!(
+                "CREATE SCHEMA {schema}; SET search_path TO {schema};"
+            ))
+            .await
+            .unwrap();
+        client
+            .batch_execute(include_str!("../db/schema.sql"))
+            .await
+            .unwrap();
+        client
+    }
+
+    async fn drop_schema(client: &tokio_postgres::Client, schema: &str) {
+        client
+            .batch_execute(&format!("DROP SCHEMA {schema} CASCADE"))
+            .await
+            .unwrap();
+    }
+
+    fn mail_count(dir: &std::path::Path) -> usize {
+        std::fs::read_dir(dir).map_or(0, |entries| entries.count())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the Postgres server of docker-compose.yml"]
+    async fn test_record_login_failure() {
+        let schema = format!("test_{}", tokens::new_token());
+        let client = test_client(&schema).await;
+        let dir = std::env::temp_dir().join(format!("lockout-test-{}", tokens::new_token()));
+        let outbox = Outbox::new(
+            Arc::new(FileMailer::new(&dir, "App <noreply@localhost>")),
+            "http://localhost:8000/",
+        );
+        let throttle = LoginThrottle::new(Arc::new(MemoryAttemptStore::new()), 1);
+        client
+            .execute(
+                "INSERT into accounts (email, password, active) VALUES($1,'',true)",
+                &[&"john@example.com"],
+            )
+            .await
+            .unwrap();
+
+        // Locked out like an account, but nothing to unlock or email.
+        let res =
+            record_login_failure(&client, &throttle, &outbox, "jane@example.com", "127.0.0.1")
+                .await;
+        assert_eq!(res, Ok(true));
+        assert_eq!(mail_count(&dir), 0);
+
+        let res =
+            record_login_failure(&client, &throttle, &outbox, "john@example.com", "127.0.0.1")
+                .await;
+        assert_eq!(res, Ok(true));
+        assert_eq!(mail_count(&dir), 1);
+        let locked = client
+            .query_one(
+                "select locked_at IS NOT NULL AS locked FROM accounts where email = $1",
+                &[&"john@example.com"],
+            )
+            .await
+            .unwrap()
+            .get::<_, bool>("locked");
+        assert!(locked);
+
+        drop_schema(&client, &schema).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
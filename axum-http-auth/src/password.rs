@@ -0,0 +1,181 @@
+//! Password hashing with Argon2.
+//!
+//! Hashes are stored as PHC strings, e.g. `$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>`, which
+//! carry the salt and parameters they were made with, so verifying needs nothing else.
+//!
+//! An Argon2id run takes tens of milliseconds, so async code should use [`spawn_hash`] and
+//! [`spawn_verify`], which run on the blocking thread pool.
+
+use argon2::{Config, Variant, Version};
+use rand::RngCore;
+use std::sync::OnceLock;
+
+const SALT_LEN: usize = 16;
+
+/// The outcome of checking a login against the stored hash of its account.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verification {
+    /// The password is wrong, or there is no such account.
+    Invalid,
+    Valid,
+    /// The password is right, and the stored hash should be replaced with this one.
+    Rehashed(String),
+}
+
+/// The parameters new hashes are made with, as recommended by OWASP for Argon2id.
+fn config() -> Config<'static> {
+    Config {
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+        mem_cost: 19456,
+        time_cost: 2,
+        lanes: 1,
+        ..Config::default()
+    }
+}
+
+/// Hash `password` with a random salt of its own.
+pub fn hash(password: &str) -> argon2::Result<String> {
+    let mut salt = [0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    argon2::hash_encoded(password.as_bytes(), &salt, &config())
+}
+
+/// Whether `encoded` was made with other parameters than [`hash`] uses now.
+///
+/// That includes the hashes from before salts were random, which all used the Argon2i defaults
+/// and the salt `salt_goes_here`.
+pub fn needs_rehash(encoded: &str) -> bool {
+    let config = config();
+    let params = format!(
+        "${}$v={}$m={},t={},p={}$",
+        config.variant, config.version, config.mem_cost, config.time_cost, config.lanes,
+    );
+
+    !encoded.starts_with(&params)
+}
+
+/// Check `password` against the `stored` hash of the account being logged into, if it exists.
+///
+/// Unknown accounts take about as long to reject as wrong passwords, so that the response time
+/// doesn't tell which emails have an account.
+pub fn verify(stored: Option<&str>, password: &str) -> argon2::Result<Verification> {
+    let Some(stored) = stored else {
+        argon2::verify_encoded(dummy_hash()?, password.as_bytes())?;
+        return Ok(Verification::Invalid);
+    };
+
+    if !argon2::verify_encoded(stored, password.as_bytes())? {
+        return Ok(Verification::Invalid);
+    }
+
+    if needs_rehash(stored) {
+        return Ok(Verification::Rehashed(hash(password)?));
+    }
+
+    Ok(Verification::Valid)
+}
+
+/// [`hash`] on the blocking thread pool.
+pub async fn spawn_hash(password: String) -> argon2::Result<String> {
+    spawn_blocking(move || hash(&password)).await
+}
+
+/// [`verify`] on the blocking thread pool.
+pub async fn spawn_verify(
+    stored: Option<String>,
+    password: String,
+) -> argon2::Result<Verification> {
+    spawn_blocking(move || verify(stored.as_deref(), &password)).await
+}
+
+async fn spawn_blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res,
+        // A panic while hashing is one of the caller.
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+fn dummy_hash() -> argon2::Result<&'static str> {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+    if let Some(hash) = DUMMY_HASH.get() {
+        return Ok(hash);
+    }
+
+    let dummy = hash("dummy password")?;
+    Ok(DUMMY_HASH.get_or_init(|| dummy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_uses_random_salts() {
+        let first = hash("password123").unwrap();
+        let second = hash("password123").unwrap();
+
+        assert!(first.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+        assert_ne!(first, second);
+        assert!(!needs_rehash(&first));
+    }
+
+    #[test]
+    fn test_verify() {
+        let stored = hash("password123").unwrap();
+
+        assert_eq!(
+            verify(Some(&stored), "password123").unwrap(),
+            Verification::Valid
+        );
+        assert_eq!(
+            verify(Some(&stored), "password124").unwrap(),
+            Verification::Invalid
+        );
+        assert_eq!(verify(None, "password123").unwrap(), Verification::Invalid);
+        assert!(verify(Some("not a hash"), "password123").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spawn_hash_and_verify() {
+        let stored = spawn_hash("password123".to_string()).await.unwrap();
+
+        assert_eq!(
+            spawn_verify(Some(stored), "password123".to_string())
+                .await
+                .unwrap(),
+            Verification::Valid
+        );
+        assert_eq!(
+            spawn_verify(None, "password123".to_string()).await.unwrap(),
+            Verification::Invalid
+        );
+    }
+
+    #[test]
+    fn test_verify_upgrades_legacy_hashes() {
+        let legacy =
+            argon2::hash_encoded(b"password123", b"salt_goes_here", &Config::default()).unwrap();
+        assert!(needs_rehash(&legacy));
+
+        assert_eq!(
+            verify(Some(&legacy), "password124").unwrap(),
+            Verification::Invalid
+        );
+
+        let Verification::Rehashed(upgraded) = verify(Some(&legacy), "password123").unwrap() else {
+            panic!("expected the legacy hash to be upgraded");
+        };
+        assert!(!needs_rehash(&upgraded));
+        assert_eq!(
+            verify(Some(&upgraded), "password123").unwrap(),
+            Verification::Valid
+        );
+    }
+}
@@ -0,0 +1,470 @@
+//! Session storage, timeouts and the sessions each account is logged in with.
+//!
+//! `SessionLayer` only needs a `SessionStore`, but listing and revoking the sessions of an
+//! account needs the stores to know whose sessions they hold, so each backend here also keeps
+//! them by the `email` they are logged into.
+
+mod memory_store;
+mod postgres_store;
+mod redis_store;
+
+use axum::{
+    extract::State,
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+use axum_sessions::{
+    async_session::{self, async_trait, Session, SessionStore},
+    SessionHandle,
+};
+use std::cmp::Reverse;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::csrf;
+
+pub use memory_store::MemorySessionStore;
+pub use postgres_store::PostgresSessionStore;
+pub use redis_store::RedisSessionStore;
+
+const CREATED_AT: &str = "created_at";
+const LAST_SEEN: &str = "last_seen";
+const USER_AGENT: &str = "user_agent";
+/// How stale `last_seen` may get before a request updates it, so that not every request has to
+/// write the session back.
+const TOUCH_INTERVAL: u64 = 60;
+
+/// A session store that can also find the sessions logged into an account.
+#[async_trait]
+pub trait AccountSessionStore: SessionStore {
+    /// The unexpired sessions logged into `email`.
+    async fn account_sessions(&self, email: &str) -> async_session::Result<Vec<Session>>;
+
+    /// Destroy the session `id`, if it is logged into `email`.
+    async fn revoke_session(&self, email: &str, id: &str) -> async_session::Result;
+
+    /// Drop expired sessions, for stores that don't expire them on their own.
+    async fn cleanup(&self) -> async_session::Result {
+        Ok(())
+    }
+}
+
+/// The store configured by `SESSION_STORE`.
+#[derive(Clone, Debug)]
+pub enum SessionBackend {
+    Memory(MemorySessionStore),
+    Postgres(PostgresSessionStore),
+    Redis(RedisSessionStore),
+}
+
+#[async_trait]
+impl SessionStore for SessionBackend {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        match self {
+            SessionBackend::Memory(store) => store.load_session(cookie_value).await,
+            SessionBackend::Postgres(store) => store.load_session(cookie_value).await,
+            SessionBackend::Redis(store) => store.load_session(cookie_value).await,
+        }
+    }
+
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        match self {
+            SessionBackend::Memory(store) => store.store_session(session).await,
+            SessionBackend::Postgres(store) => store.store_session(session).await,
+            SessionBackend::Redis(store) => store.store_session(session).await,
+        }
+    }
+
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        match self {
+            SessionBackend::Memory(store) => store.destroy_session(session).await,
+            SessionBackend::Postgres(store) => store.destroy_session(session).await,
+            SessionBackend::Redis(store) => store.destroy_session(session).await,
+        }
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        match self {
+            SessionBackend::Memory(store) => store.clear_store().await,
+            SessionBackend::Postgres(store) => store.clear_store().await,
+            SessionBackend::Redis(store) => store.clear_store().await,
+        }
+    }
+}
+
+#[async_trait]
+impl AccountSessionStore for SessionBackend {
+    async fn account_sessions(&self, email: &str) -> async_session::Result<Vec<Session>> {
+        match self {
+            SessionBackend::Memory(store) => store.account_sessions(email).await,
+            SessionBackend::Postgres(store) => store.account_sessions(email).await,
+            SessionBackend::Redis(store) => store.account_sessions(email).await,
+        }
+    }
+
+    async fn revoke_session(&self, email: &str, id: &str) -> async_session::Result {
+        match self {
+            SessionBackend::Memory(store) => store.revoke_session(email, id).await,
+            SessionBackend::Postgres(store) => store.revoke_session(email, id).await,
+            SessionBackend::Redis(store) => store.revoke_session(email, id).await,
+        }
+    }
+
+    async fn cleanup(&self) -> async_session::Result {
+        match self {
+            SessionBackend::Memory(store) => store.cleanup().await,
+            SessionBackend::Postgres(store) => store.cleanup().await,
+            SessionBackend::Redis(store) => store.cleanup().await,
+        }
+    }
+}
+
+/// A session an account is logged in with, as listed on the account's sessions page.
+#[derive(Debug)]
+pub struct ActiveSession {
+    pub id: String,
+    pub user_agent: String,
+    pub created_at: u64,
+    pub last_seen: u64,
+    /// Whether it's the session of the request listing them.
+    pub current: bool,
+}
+
+impl ActiveSession {
+    pub fn created_ago(&self) -> String {
+        ago(self.created_at)
+    }
+
+    pub fn last_seen_ago(&self) -> String {
+        ago(self.last_seen)
+    }
+}
+
+/// The session store, with the timeouts sessions expire after.
+#[derive(Clone, Debug)]
+pub struct Sessions {
+    pub store: SessionBackend,
+    /// How long a session lasts without requests.
+    pub idle_timeout: Duration,
+    /// How long a session lasts at most, however active it is.
+    pub absolute_timeout: Duration,
+}
+
+impl Sessions {
+    /// Give `session` a new ID, e.g. when logging in, so that an ID planted or seen before
+    /// doesn't get access to the account. It also starts over the absolute timeout, and drops the
+    /// CSRF token for the next page to get a new one.
+    pub async fn rotate(&self, session: &mut Session) -> async_session::Result {
+        self.store.destroy_session(session.clone()).await?;
+        session.regenerate();
+        session.remove(csrf::SESSION_KEY);
+        session.insert(CREATED_AT, now())?;
+        Ok(())
+    }
+
+    /// The sessions `email` is logged in with, most recently used first.
+    pub async fn list(
+        &self,
+        email: &str,
+        current: &Session,
+    ) -> async_session::Result<Vec<ActiveSession>> {
+        let mut sessions = self
+            .store
+            .account_sessions(email)
+            .await?
+            .into_iter()
+            .map(|session| ActiveSession {
+                id: session.id().to_string(),
+                user_agent: session.get(USER_AGENT).unwrap_or_default(),
+                created_at: session.get(CREATED_AT).unwrap_or_default(),
+                last_seen: session.get(LAST_SEEN).unwrap_or_default(),
+                current: session.id() == current.id(),
+            })
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|session| Reverse(session.last_seen));
+        Ok(sessions)
+    }
+
+    /// Log out the session `id` of `email`.
+    pub async fn revoke(&self, email: &str, id: &str) -> async_session::Result {
+        self.store.revoke_session(email, id).await
+    }
+
+    /// Log out every session of `email`, but the one with the ID `except`.
+    pub async fn revoke_all(&self, email: &str, except: Option<&str>) -> async_session::Result {
+        for session in self.store.account_sessions(email).await? {
+            if Some(session.id()) != except {
+                self.store.revoke_session(email, session.id()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop expired sessions every `period`, for as long as the app runs.
+    pub async fn cleanup_every(self, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.store.cleanup().await {
+                tracing::error!("failed to clean up sessions: {}", err);
+            }
+        }
+    }
+
+    /// How much longer than `now` a session created and last seen at these times lasts.
+    fn remaining(&self, created_at: u64, last_seen: u64, now: u64) -> Option<Duration> {
+        let idle_deadline = last_seen + self.idle_timeout.as_secs();
+        let absolute_deadline = created_at + self.absolute_timeout.as_secs();
+        let deadline = idle_deadline.min(absolute_deadline);
+        (deadline > now).then(|| Duration::from_secs(deadline - now))
+    }
+}
+
+/// Middleware replacing sessions that timed out with new ones, and keeping track of when the
+/// others were last used.
+///
+/// Sessions only start timing out once they hold something, e.g. a CSRF token, so that requests
+/// that don't need one don't get one stored. It has to be layered inside the `SessionLayer`, and
+/// around the layers and handlers putting things into sessions.
+pub async fn enforce_timeouts<B>(
+    State(sessions): State<Sessions>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let handle = req
+        .extensions()
+        .get::<SessionHandle>()
+        .cloned()
+        .expect("the session layer must wrap the session timeout layer");
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|header| header.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    {
+        let mut session = handle.write().await;
+        let now = now();
+        let created_at = session.get::<u64>(CREATED_AT);
+        let last_seen = session.get::<u64>(LAST_SEEN);
+
+        if let Some((created_at, last_seen)) = created_at.zip(last_seen) {
+            match sessions.remaining(created_at, last_seen, now) {
+                Some(_) if now.saturating_sub(last_seen) < TOUCH_INTERVAL => (),
+                Some(_) => touch(&mut session, &sessions, created_at, now),
+                None => {
+                    tracing::debug!("session {} timed out", session.id());
+                    let expired = std::mem::take(&mut *session);
+                    if let Err(err) = sessions.store.destroy_session(expired).await {
+                        tracing::error!("failed to destroy a timed out session: {}", err);
+                    }
+                }
+            }
+        }
+    }
+
+    let res = next.run(req).await;
+
+    let mut session = handle.write().await;
+    let started = session.get::<u64>(LAST_SEEN).is_some();
+    if !started && session.len() > 0 && !session.is_destroyed() {
+        start(&mut session, &sessions, &user_agent, now());
+    }
+    res
+}
+
+fn start(session: &mut Session, sessions: &Sessions, user_agent: &str, now: u64) {
+    session
+        .insert(CREATED_AT, now)
+        .expect("Session could not be created.");
+    session
+        .insert(USER_AGENT, user_agent)
+        .expect("Session could not be created.");
+    touch(session, sessions, now, now);
+}
+
+/// Mark `session` as used `now`, which pushes back its expiry in the store.
+fn touch(session: &mut Session, sessions: &Sessions, created_at: u64, now: u64) {
+    session
+        .insert(LAST_SEEN, now)
+        .expect("Session could not be created.");
+    if let Some(remaining) = sessions.remaining(created_at, now, now) {
+        session.expire_in(remaining);
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn ago(time: u64) -> String {
+    let secs = now().saturating_sub(time);
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => plural(secs / 60, "minute"),
+        3600..=86399 => plural(secs / 3600, "hour"),
+        _ => plural(secs / 86400, "day"),
+    }
+}
+
+fn plural(count: u64, unit: &str) -> String {
+    if count == 1 {
+        format!("1 {} ago", unit)
+    } else {
+        format!("{} {}s ago", count, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions() -> Sessions {
+        Sessions {
+            store: SessionBackend::Memory(MemorySessionStore::new()),
+            idle_timeout: Duration::from_secs(30 * 60),
+            absolute_timeout: Duration::from_secs(12 * 60 * 60),
+        }
+    }
+
+    #[test]
+    fn test_remaining() {
+        let sessions = sessions();
+        let created_at = 1_000_000;
+
+        assert_eq!(
+            sessions.remaining(created_at, created_at, created_at + 60),
+            Some(Duration::from_secs(29 * 60))
+        );
+        // Idle for too long.
+        assert_eq!(
+            sessions.remaining(created_at, created_at, created_at + 30 * 60),
+            None
+        );
+        // Active, but too old.
+        let late = created_at + 12 * 60 * 60;
+        assert_eq!(
+            sessions.remaining(created_at, late - 10, late - 5),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(sessions.remaining(created_at, late - 10, late), None);
+    }
+
+    #[tokio::test]
+    async fn test_rotate() {
+        let sessions = sessions();
+        let mut session = Session::new();
+        session.insert("email", "john@example.com").unwrap();
+        session.insert(csrf::SESSION_KEY, "token").unwrap();
+        let cookie_value = sessions
+            .store
+            .store_session(session)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut session = sessions
+            .store
+            .load_session(cookie_value.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        let old_id = session.id().to_string();
+
+        sessions.rotate(&mut session).await.unwrap();
+
+        assert_ne!(session.id(), old_id);
+        assert_eq!(
+            session.get::<String>("email").as_deref(),
+            Some("john@example.com")
+        );
+        assert_eq!(session.get::<String>(csrf::SESSION_KEY), None);
+        assert!(sessions
+            .store
+            .load_session(cookie_value)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_enforce_timeouts() {
+        use axum::{body::Body, middleware, routing::get, Extension, Router};
+        use axum_sessions::extractors::WritableSession;
+        use std::sync::Arc;
+        use tokio::sync::RwLock;
+        use tower::ServiceExt;
+
+        let handle: SessionHandle = Arc::new(RwLock::new(Session::new()));
+        let app = Router::new()
+            .route("/plain", get(|| async { "plain" }))
+            .route(
+                "/login",
+                get(|mut session: WritableSession| async move {
+                    session.insert("email", "john@example.com").unwrap();
+                }),
+            )
+            .layer(middleware::from_fn_with_state(sessions(), enforce_timeouts))
+            .layer(Extension(handle.clone()));
+        let get = |uri| {
+            let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(req)
+        };
+
+        // Nothing to keep, so nothing to time out either.
+        get("/plain").await.unwrap();
+        assert_eq!(handle.read().await.len(), 0);
+
+        get("/login").await.unwrap();
+        let session = handle.read().await;
+        assert!(session.get::<u64>(CREATED_AT).is_some());
+        assert!(session.get::<u64>(LAST_SEEN).is_some());
+        assert!(session.expiry().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_list_and_revoke() {
+        let sessions = sessions();
+        let mut current = Session::new();
+        start(&mut current, &sessions, "Firefox", now());
+        current.insert("email", "john@example.com").unwrap();
+        let mut other = Session::new();
+        start(&mut other, &sessions, "Safari", now() - 120);
+        other.insert("email", "john@example.com").unwrap();
+        let mut someone_else = Session::new();
+        someone_else.insert("email", "jane@example.com").unwrap();
+        for session in [&current, &other, &someone_else] {
+            sessions.store.store_session(session.clone()).await.unwrap();
+        }
+
+        let listed = sessions.list("john@example.com", &current).await.unwrap();
+        assert_eq!(listed.len(), 2);
+        assert!(listed[0].current);
+        assert_eq!(listed[0].user_agent, "Firefox");
+        assert_eq!(listed[1].last_seen_ago(), "2 minutes ago");
+
+        // Only the sessions of the account can be revoked.
+        sessions
+            .revoke("jane@example.com", other.id())
+            .await
+            .unwrap();
+        assert_eq!(
+            sessions
+                .list("john@example.com", &current)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        sessions
+            .revoke("john@example.com", other.id())
+            .await
+            .unwrap();
+        let listed = sessions.list("john@example.com", &current).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, current.id());
+    }
+}
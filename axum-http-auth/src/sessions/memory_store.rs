@@ -0,0 +1,78 @@
+use axum_sessions::async_session::{self, async_trait, Session, SessionStore};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use super::AccountSessionStore;
+
+/// Sessions kept in memory, which are lost on restart. Meant for tests and development.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: Arc<RwLock<HashMap<String, Session>>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let sessions = self.sessions.read().unwrap();
+        Ok(sessions.get(&id).cloned().and_then(Session::validate))
+    }
+
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(session.id().to_string(), session.clone());
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        self.sessions.write().unwrap().remove(session.id());
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        self.sessions.write().unwrap().clear();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AccountSessionStore for MemorySessionStore {
+    async fn account_sessions(&self, email: &str) -> async_session::Result<Vec<Session>> {
+        let sessions = self.sessions.read().unwrap();
+        Ok(sessions
+            .values()
+            .filter(|session| {
+                !session.is_expired() && session.get::<String>("email").as_deref() == Some(email)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn revoke_session(&self, email: &str, id: &str) -> async_session::Result {
+        let mut sessions = self.sessions.write().unwrap();
+        if sessions
+            .get(id)
+            .is_some_and(|session| session.get::<String>("email").as_deref() == Some(email))
+        {
+            sessions.remove(id);
+        }
+        Ok(())
+    }
+
+    async fn cleanup(&self) -> async_session::Result {
+        self.sessions
+            .write()
+            .unwrap()
+            .retain(|_, session| !session.is_expired());
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use axum_sessions::async_session::{self, async_trait, serde_json, Session, SessionStore};
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use tokio_postgres::NoTls;
+
+use super::AccountSessionStore;
+
+/// Sessions in the `sessions` table, shared with the app's connection pool.
+#[derive(Clone, Debug)]
+pub struct PostgresSessionStore {
+    pool: Pool<PostgresConnectionManager<NoTls>>,
+}
+
+impl PostgresSessionStore {
+    pub fn new(pool: Pool<PostgresConnectionManager<NoTls>>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresSessionStore {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let conn = self.pool.get().await?;
+        let row = conn
+            .query_opt(
+                "select data FROM sessions where id = $1 AND (expires IS NULL OR expires > now())",
+                &[&id],
+            )
+            .await?;
+
+        match row {
+            Some(row) => {
+                let session = serde_json::from_str::<Session>(row.get("data"))?;
+                Ok(session.validate())
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        let email = session.get::<String>("email");
+        let expires = session.expiry().map(|expiry| expiry.timestamp() as f64);
+        let data = serde_json::to_string(&session)?;
+
+        let conn = self.pool.get().await?;
+        conn.execute(
+            "INSERT into sessions (id, email, expires, data) VALUES($1,$2,to_timestamp($3),$4)
+             ON CONFLICT (id) DO UPDATE
+             SET email = EXCLUDED.email, expires = EXCLUDED.expires, data = EXCLUDED.data",
+            &[&session.id(), &email, &expires, &data],
+        )
+        .await?;
+
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        let conn = self.pool.get().await?;
+        conn.execute("DELETE FROM sessions WHERE id = $1", &[&session.id()])
+            .await?;
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        let conn = self.pool.get().await?;
+        conn.execute("DELETE FROM sessions", &[]).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AccountSessionStore for PostgresSessionStore {
+    async fn account_sessions(&self, email: &str) -> async_session::Result<Vec<Session>> {
+        let conn = self.pool.get().await?;
+        let rows = conn
+            .query(
+                "select data FROM sessions
+                 where email = $1 AND (expires IS NULL OR expires > now())",
+                &[&email],
+            )
+            .await?;
+
+        let mut sessions = Vec::with_capacity(rows.len());
+        for row in rows {
+            sessions.push(serde_json::from_str::<Session>(row.get("data"))?);
+        }
+        Ok(sessions)
+    }
+
+    async fn revoke_session(&self, email: &str, id: &str) -> async_session::Result {
+        let conn = self.pool.get().await?;
+        conn.execute(
+            "DELETE FROM sessions WHERE id = $1 AND email = $2",
+            &[&id, &email],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn cleanup(&self) -> async_session::Result {
+        let conn = self.pool.get().await?;
+        conn.execute("DELETE FROM sessions WHERE expires < now()", &[])
+            .await?;
+        Ok(())
+    }
+}
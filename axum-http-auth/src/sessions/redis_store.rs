@@ -0,0 +1,126 @@
+use axum_sessions::async_session::{self, async_trait, serde_json, Session, SessionStore};
+use redis::{AsyncCommands, Client};
+
+use super::AccountSessionStore;
+
+/// Sessions in Redis, which expires them on its own. Each account's session IDs are kept in a
+/// set too, which is pruned of expired sessions when listed.
+#[derive(Clone, Debug)]
+pub struct RedisSessionStore {
+    client: Client,
+}
+
+impl RedisSessionStore {
+    pub fn new(url: &str) -> redis::RedisResult<Self> {
+        Ok(Self {
+            client: Client::open(url)?,
+        })
+    }
+
+    async fn connection(&self) -> redis::RedisResult<redis::aio::Connection> {
+        self.client.get_async_connection().await
+    }
+}
+
+fn session_key(id: &str) -> String {
+    format!("session:{}", id)
+}
+
+fn account_key(email: &str) -> String {
+    format!("account_sessions:{}", email)
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let mut conn = self.connection().await?;
+        let data: Option<String> = conn.get(session_key(&id)).await?;
+
+        match data {
+            Some(data) => Ok(serde_json::from_str::<Session>(&data)?.validate()),
+            None => Ok(None),
+        }
+    }
+
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        let key = session_key(session.id());
+        let data = serde_json::to_string(&session)?;
+
+        let mut conn = self.connection().await?;
+        match session.expires_in() {
+            Some(expires_in) => {
+                let secs = expires_in.as_secs().max(1) as usize;
+                conn.set_ex::<_, _, ()>(&key, data, secs).await?
+            }
+            None => conn.set::<_, _, ()>(&key, data).await?,
+        }
+        if let Some(email) = session.get::<String>("email") {
+            conn.sadd::<_, _, ()>(account_key(&email), session.id())
+                .await?;
+        }
+
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        let mut conn = self.connection().await?;
+        conn.del::<_, ()>(session_key(session.id())).await?;
+        if let Some(email) = session.get::<String>("email") {
+            conn.srem::<_, _, ()>(account_key(&email), session.id())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        let mut conn = self.connection().await?;
+        for pattern in ["session:*", "account_sessions:*"] {
+            let keys: Vec<String> = conn.keys(pattern).await?;
+            if !keys.is_empty() {
+                conn.del::<_, ()>(keys).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AccountSessionStore for RedisSessionStore {
+    async fn account_sessions(&self, email: &str) -> async_session::Result<Vec<Session>> {
+        let mut conn = self.connection().await?;
+        let ids: Vec<String> = conn.smembers(account_key(email)).await?;
+
+        let mut sessions = Vec::with_capacity(ids.len());
+        for id in ids {
+            let data: Option<String> = conn.get(session_key(&id)).await?;
+            let session = match data {
+                Some(data) => serde_json::from_str::<Session>(&data)?.validate(),
+                None => None,
+            };
+            // Gone, or logged out and into another account since.
+            match session {
+                Some(session) if session.get::<String>("email").as_deref() == Some(email) => {
+                    sessions.push(session)
+                }
+                _ => conn.srem::<_, _, ()>(account_key(email), &id).await?,
+            }
+        }
+        Ok(sessions)
+    }
+
+    async fn revoke_session(&self, email: &str, id: &str) -> async_session::Result {
+        let mut conn = self.connection().await?;
+        let data: Option<String> = conn.get(session_key(id)).await?;
+        let Some(data) = data else {
+            return Ok(());
+        };
+
+        let session = serde_json::from_str::<Session>(&data)?;
+        if session.get::<String>("email").as_deref() == Some(email) {
+            self.destroy_session(session).await?;
+        }
+        Ok(())
+    }
+}
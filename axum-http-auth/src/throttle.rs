@@ -0,0 +1,391 @@
+//! Throttling failed logins.
+//!
+//! Failures are counted per email and per IP address. After a few of them, each further attempt
+//! has to wait twice as long as the one before, and an email that keeps failing gets its account
+//! locked. Counters are forgotten an hour after their last failure.
+//!
+//! The counters are kept in the same kind of store as sessions, so they only survive restarts
+//! with Postgres or Redis.
+
+use axum::async_trait;
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio_postgres::NoTls;
+
+/// Seconds after the last failure that a counter starts over.
+const WINDOW: u64 = 60 * 60;
+/// Failures of an email that don't slow down its next attempt yet.
+const EMAIL_FREE_FAILURES: u32 = 3;
+/// Failures from an IP address that don't slow down its next attempt yet. Higher than for emails,
+/// since many people can share an address.
+const IP_FREE_FAILURES: u32 = 10;
+const BASE_DELAY: Duration = Duration::from_secs(2);
+const MAX_DELAY: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Error)]
+pub enum ThrottleError {
+    #[error("failed to get a connection: {0}")]
+    Pool(#[from] bb8::RunError<tokio_postgres::Error>),
+
+    #[error(transparent)]
+    Postgres(#[from] tokio_postgres::Error),
+
+    #[error(transparent)]
+    Redis(#[from] redis::RedisError),
+}
+
+/// The failed attempts recorded for a key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Attempts {
+    pub failures: u32,
+    /// When the last one was, in seconds since the Unix epoch.
+    pub last_failure: u64,
+}
+
+#[async_trait]
+pub trait AttemptStore: Send + Sync {
+    /// The failures of `key` within the window before `now`.
+    async fn attempts(&self, key: &str, now: u64) -> Result<Attempts, ThrottleError>;
+
+    /// Count another failure of `key` at `now`.
+    async fn record_failure(&self, key: &str, now: u64) -> Result<Attempts, ThrottleError>;
+
+    /// Forget the failures of `key`.
+    async fn reset(&self, key: &str) -> Result<(), ThrottleError>;
+}
+
+/// Counters kept in memory, which are lost on restart.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryAttemptStore {
+    attempts: Arc<Mutex<HashMap<String, Attempts>>>,
+}
+
+impl MemoryAttemptStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AttemptStore for MemoryAttemptStore {
+    async fn attempts(&self, key: &str, now: u64) -> Result<Attempts, ThrottleError> {
+        let attempts = self.attempts.lock().unwrap();
+        Ok(attempts
+            .get(key)
+            .copied()
+            .filter(|attempts| now < attempts.last_failure + WINDOW)
+            .unwrap_or_default())
+    }
+
+    async fn record_failure(&self, key: &str, now: u64) -> Result<Attempts, ThrottleError> {
+        let mut attempts = self.attempts.lock().unwrap();
+        attempts.retain(|_, attempts| now < attempts.last_failure + WINDOW);
+        let attempts = attempts.entry(key.to_string()).or_default();
+        attempts.failures += 1;
+        attempts.last_failure = now;
+        Ok(*attempts)
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), ThrottleError> {
+        self.attempts.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Counters in the `login_attempts` table.
+#[derive(Clone, Debug)]
+pub struct PostgresAttemptStore {
+    pool: Pool<PostgresConnectionManager<NoTls>>,
+}
+
+impl PostgresAttemptStore {
+    pub fn new(pool: Pool<PostgresConnectionManager<NoTls>>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AttemptStore for PostgresAttemptStore {
+    async fn attempts(&self, key: &str, now: u64) -> Result<Attempts, ThrottleError> {
+        let conn = self.pool.get().await?;
+        let row = conn
+            .query_opt(
+                "select failures, last_failure FROM login_attempts
+                 where key = $1 AND last_failure > $2",
+                &[&key, &(now.saturating_sub(WINDOW) as i64)],
+            )
+            .await?;
+        Ok(row.map_or_else(Attempts::default, |row| Attempts {
+            failures: row.get::<_, i32>("failures") as u32,
+            last_failure: row.get::<_, i64>("last_failure") as u64,
+        }))
+    }
+
+    async fn record_failure(&self, key: &str, now: u64) -> Result<Attempts, ThrottleError> {
+        let conn = self.pool.get().await?;
+        let row = conn
+            .query_one(
+                "INSERT into login_attempts (key, failures, last_failure) VALUES($1,1,$2)
+                 ON CONFLICT (key) DO UPDATE SET
+                 failures = CASE WHEN login_attempts.last_failure > $3
+                     THEN login_attempts.failures + 1 ELSE 1 END,
+                 last_failure = EXCLUDED.last_failure
+                 RETURNING failures, last_failure",
+                &[&key, &(now as i64), &(now.saturating_sub(WINDOW) as i64)],
+            )
+            .await?;
+        Ok(Attempts {
+            failures: row.get::<_, i32>("failures") as u32,
+            last_failure: row.get::<_, i64>("last_failure") as u64,
+        })
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), ThrottleError> {
+        let conn = self.pool.get().await?;
+        conn.execute("DELETE FROM login_attempts WHERE key = $1", &[&key])
+            .await?;
+        Ok(())
+    }
+}
+
+/// Counters in Redis hashes, which expire a window after their last failure.
+#[derive(Clone, Debug)]
+pub struct RedisAttemptStore {
+    client: redis::Client,
+}
+
+impl RedisAttemptStore {
+    pub fn new(url: &str) -> redis::RedisResult<Self> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+        })
+    }
+}
+
+fn redis_key(key: &str) -> String {
+    format!("login_attempts:{}", key)
+}
+
+#[async_trait]
+impl AttemptStore for RedisAttemptStore {
+    async fn attempts(&self, key: &str, _now: u64) -> Result<Attempts, ThrottleError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let (failures, last_failure): (Option<u32>, Option<u64>) = redis::cmd("HMGET")
+            .arg(redis_key(key))
+            .arg("failures")
+            .arg("last_failure")
+            .query_async(&mut conn)
+            .await?;
+        Ok(Attempts {
+            failures: failures.unwrap_or_default(),
+            last_failure: last_failure.unwrap_or_default(),
+        })
+    }
+
+    async fn record_failure(&self, key: &str, now: u64) -> Result<Attempts, ThrottleError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let key = redis_key(key);
+        let (failures,): (u32,) = redis::pipe()
+            .atomic()
+            .hincr(&key, "failures", 1)
+            .hset(&key, "last_failure", now)
+            .ignore()
+            .expire(&key, WINDOW as usize)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(Attempts {
+            failures,
+            last_failure: now,
+        })
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), ThrottleError> {
+        let mut conn = self.client.get_async_connection().await?;
+        redis::cmd("DEL")
+            .arg(redis_key(key))
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Whether a login may go ahead.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Throttled {
+    No,
+    /// Too soon after the last failures.
+    Wait(Duration),
+    /// The email failed too often, which locks its account.
+    Locked,
+}
+
+/// The counters of failed logins, and when they start slowing logins down.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    store: Arc<dyn AttemptStore>,
+    /// Failures of an email that lock its account.
+    pub lockout_threshold: u32,
+}
+
+impl LoginThrottle {
+    pub fn new(store: Arc<dyn AttemptStore>, lockout_threshold: u32) -> Self {
+        Self {
+            store,
+            lockout_threshold,
+        }
+    }
+
+    /// Whether logging into `email` from `ip` may be tried now.
+    pub async fn check(&self, email: &str, ip: &str) -> Result<Throttled, ThrottleError> {
+        let now = now();
+        let by_email = self.store.attempts(&email_key(email), now).await?;
+        if by_email.failures >= self.lockout_threshold {
+            return Ok(Throttled::Locked);
+        }
+        let by_ip = self.store.attempts(&ip_key(ip), now).await?;
+
+        let wait = remaining(by_email, EMAIL_FREE_FAILURES, now).max(remaining(
+            by_ip,
+            IP_FREE_FAILURES,
+            now,
+        ));
+        if wait.is_zero() {
+            Ok(Throttled::No)
+        } else {
+            Ok(Throttled::Wait(wait))
+        }
+    }
+
+    /// Count a failed login into `email` from `ip`, and tell whether the account should be
+    /// locked now.
+    pub async fn record_failure(&self, email: &str, ip: &str) -> Result<bool, ThrottleError> {
+        let now = now();
+        self.store.record_failure(&ip_key(ip), now).await?;
+        let by_email = self.store.record_failure(&email_key(email), now).await?;
+        Ok(by_email.failures >= self.lockout_threshold)
+    }
+
+    /// Forget the failures of `email`, after logging in or unlocking it. Those of IP addresses
+    /// stay, so that one account that works doesn't help guessing the passwords of others.
+    pub async fn reset(&self, email: &str) -> Result<(), ThrottleError> {
+        self.store.reset(&email_key(email)).await
+    }
+}
+
+fn email_key(email: &str) -> String {
+    format!("email:{}", email.to_lowercase())
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+/// How long to wait after `failures` before trying again, doubling with each one past the free
+/// ones.
+fn backoff(failures: u32, free_failures: u32) -> Duration {
+    if failures < free_failures {
+        return Duration::ZERO;
+    }
+
+    let doublings = (failures - free_failures).min(16);
+    (BASE_DELAY * 2u32.pow(doublings)).min(MAX_DELAY)
+}
+
+/// How much of the backoff of `attempts` is left at `now`.
+fn remaining(attempts: Attempts, free_failures: u32, now: u64) -> Duration {
+    let until = attempts.last_failure + backoff(attempts.failures, free_failures).as_secs();
+    Duration::from_secs(until.saturating_sub(now))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(0, 3), Duration::ZERO);
+        assert_eq!(backoff(2, 3), Duration::ZERO);
+        assert_eq!(backoff(3, 3), Duration::from_secs(2));
+        assert_eq!(backoff(4, 3), Duration::from_secs(4));
+        assert_eq!(backoff(8, 3), Duration::from_secs(64));
+        assert_eq!(backoff(20, 3), MAX_DELAY);
+        assert_eq!(backoff(u32::MAX, 3), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_remaining() {
+        let attempts = Attempts {
+            failures: 4,
+            last_failure: 1_000,
+        };
+        assert_eq!(remaining(attempts, 3, 1_001), Duration::from_secs(3));
+        assert_eq!(remaining(attempts, 3, 1_004), Duration::ZERO);
+        assert_eq!(remaining(attempts, 10, 1_001), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_login_throttle() {
+        let throttle = LoginThrottle::new(Arc::new(MemoryAttemptStore::new()), 5);
+
+        for _ in 0..EMAIL_FREE_FAILURES {
+            assert_eq!(
+                throttle
+                    .check("john@example.com", "10.0.0.1")
+                    .await
+                    .unwrap(),
+                Throttled::No
+            );
+            assert!(!throttle
+                .record_failure("john@example.com", "10.0.0.1")
+                .await
+                .unwrap());
+        }
+        assert!(matches!(
+            throttle.check("John@example.com", "10.0.0.2").await.unwrap(),
+            Throttled::Wait(wait) if wait <= BASE_DELAY && !wait.is_zero()
+        ));
+        assert_eq!(
+            throttle
+                .check("jane@example.com", "10.0.0.2")
+                .await
+                .unwrap(),
+            Throttled::No
+        );
+
+        assert!(!throttle
+            .record_failure("john@example.com", "10.0.0.1")
+            .await
+            .unwrap());
+        assert!(throttle
+            .record_failure("john@example.com", "10.0.0.1")
+            .await
+            .unwrap());
+        assert_eq!(
+            throttle
+                .check("john@example.com", "10.0.0.2")
+                .await
+                .unwrap(),
+            Throttled::Locked
+        );
+
+        throttle.reset("john@example.com").await.unwrap();
+        assert_eq!(
+            throttle
+                .check("john@example.com", "10.0.0.2")
+                .await
+                .unwrap(),
+            Throttled::No
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Single-use tokens for the links sent by email.
+//!
+//! Only a SHA-256 hash of each token is stored, so that the `account_tokens` table doesn't give
+//! away working links. Tokens are random enough that a fast hash is fine for them, unlike
+//! passwords.
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Tokens of confirmation links, which activate accounts.
+pub const CONFIRM: &str = "confirm";
+/// Tokens of the links unlocking accounts locked by failed logins.
+pub const UNLOCK: &str = "unlock";
+
+/// A new random token, 64 hex characters long.
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+/// The hash `token` is stored as.
+pub fn hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        let token = new_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, new_token());
+
+        assert_eq!(hash(&token), hash(&token.clone()));
+        assert_ne!(hash(&token), token);
+        assert_eq!(
+            hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Time-based one-time passwords (RFC 6238) as a second login factor.
+//!
+//! Secrets are stored base32 encoded, the way authenticator apps take them when typed in by hand.
+//! Along with them goes the time step of the last code accepted, so that no code works twice
+//! (RFC 6238, section 5.2).
+//! Recovery codes are for when the app is gone; they are only shown once and stored hashed like
+//! passwords.
+
+use qrcode::{render::svg, QrCode};
+use rand::{seq::SliceRandom, RngCore};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::csrf::tokens_match;
+
+/// The name authenticator apps list accounts under.
+const ISSUER: &str = "App";
+const SECRET_LEN: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+/// Lowercase letters and digits, without the ones easily mistaken for each other.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+#[derive(Debug, Error)]
+pub enum TotpError {
+    #[error("invalid TOTP secret: {0}")]
+    Secret(#[from] totp_rs::SecretParseError),
+
+    #[error("invalid TOTP parameters: {0}")]
+    Totp(#[from] totp_rs::TotpUrlError),
+
+    #[error("failed to encode QR code: {0}")]
+    QrCode(#[from] qrcode::types::QrError),
+
+    #[error("system clock is before the Unix epoch: {0}")]
+    Clock(#[from] std::time::SystemTimeError),
+}
+
+/// A random 160 bit secret, as RFC 4226 recommends, base32 encoded.
+pub fn new_secret() -> String {
+    let mut bytes = [0u8; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    Secret::Raw(bytes.to_vec()).to_encoded().to_string()
+}
+
+/// 6 digit codes every 30 seconds, which is what authenticator apps assume.
+fn totp(secret: &str, email: &str) -> Result<TOTP, TotpError> {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes()?;
+    Ok(TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        Some(ISSUER.to_string()),
+        email.to_string(),
+    )?)
+}
+
+/// The `otpauth://` URI of `secret` for `email`, rendered as an SVG QR code for apps to scan.
+pub fn qr_code_svg(secret: &str, email: &str) -> Result<String, TotpError> {
+    let url = totp(secret, email)?.get_url();
+    let svg = QrCode::new(url.as_bytes())?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    Ok(svg)
+}
+
+/// The time step of `code`, ignoring spaces in it, if it's the current one of `secret` and of a
+/// later step than `last_step`, the one of the code accepted before. Codes of the previous and
+/// next step count as current too, for clocks that are a bit off.
+pub fn check_code(
+    secret: &str,
+    email: &str,
+    code: &str,
+    last_step: Option<i64>,
+) -> Result<Option<i64>, TotpError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    check_code_at(secret, email, code, last_step, now)
+}
+
+fn check_code_at(
+    secret: &str,
+    email: &str,
+    code: &str,
+    last_step: Option<i64>,
+    now: u64,
+) -> Result<Option<i64>, TotpError> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let totp = totp(secret, email)?;
+    let current = now / totp.step;
+
+    for step in [current.saturating_sub(1), current, current + 1] {
+        let step = step as i64;
+        if last_step.is_some_and(|last_step| step <= last_step) {
+            continue;
+        }
+        if tokens_match(&totp.generate(step as u64 * totp.step), &code) {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+/// A fresh set of recovery codes, formatted like `abcde-fghjk`.
+pub fn new_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..RECOVERY_CODE_LEN)
+                .map(|_| *RECOVERY_CODE_ALPHABET.choose(&mut rng).unwrap() as char)
+                .collect();
+            let (first, second) = code.split_at(RECOVERY_CODE_LEN / 2);
+            format!("{}-{}", first, second)
+        })
+        .collect()
+}
+
+/// The form recovery codes are hashed in, so that case, dashes and spaces don't matter, or
+/// `None` if `code` can't be one.
+pub fn parse_recovery_code(code: &str) -> Option<String> {
+    let code: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let valid = code.len() == RECOVERY_CODE_LEN
+        && code.bytes().all(|b| RECOVERY_CODE_ALPHABET.contains(&b));
+    valid.then_some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_code() {
+        let secret = new_secret();
+        assert_eq!(secret.len(), 32);
+        assert_ne!(secret, new_secret());
+
+        let code = totp(&secret, "john@example.com")
+            .unwrap()
+            .generate_current()
+            .unwrap();
+        let step = check_code(&secret, "john@example.com", &code, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            check_code(
+                &secret,
+                "john@example.com",
+                &format!("{} {}", &code[..3], &code[3..]),
+                None
+            )
+            .unwrap(),
+            Some(step)
+        );
+        assert_eq!(
+            check_code(&new_secret(), "john@example.com", &code, None).unwrap(),
+            None
+        );
+        assert_eq!(
+            check_code(&secret, "john@example.com", "", None).unwrap(),
+            None
+        );
+        assert!(check_code("not base32!", "john@example.com", &code, None).is_err());
+    }
+
+    #[test]
+    fn test_codes_only_work_once() {
+        let secret = new_secret();
+        let totp = totp(&secret, "john@example.com").unwrap();
+        let now = 1_000_000 * totp.step;
+        let code = |step: u64| totp.generate(step * totp.step);
+        let check = |code: &str, last_step| {
+            check_code_at(&secret, "john@example.com", code, last_step, now).unwrap()
+        };
+
+        assert_eq!(check(&code(1_000_000), None), Some(1_000_000));
+        assert_eq!(check(&code(999_999), None), Some(999_999));
+        assert_eq!(check(&code(1_000_001), None), Some(1_000_001));
+        assert_eq!(check(&code(999_998), None), None);
+
+        // Neither the same code again, nor one of an earlier step.
+        assert_eq!(check(&code(1_000_000), Some(1_000_000)), None);
+        assert_eq!(check(&code(999_999), Some(1_000_000)), None);
+        assert_eq!(check(&code(1_000_001), Some(1_000_000)), Some(1_000_001));
+    }
+
+    #[test]
+    fn test_qr_code_svg() {
+        let svg = qr_code_svg(&new_secret(), "john@example.com").unwrap();
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("<svg"));
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = new_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert_eq!(codes[0].len(), RECOVERY_CODE_LEN + 1);
+        assert_ne!(codes[0], codes[1]);
+
+        let parsed = parse_recovery_code(&codes[0]).unwrap();
+        assert_eq!(parsed, codes[0].replace('-', ""));
+        assert_eq!(
+            parse_recovery_code(&format!(" {} ", codes[0].to_uppercase())),
+            Some(parsed)
+        );
+        assert_eq!(parse_recovery_code("123456"), None);
+        assert_eq!(parse_recovery_code("abcde-fghji"), None);
+    }
+}
@@ -5,9 +5,14 @@ use axum::{
     http::{Request, StatusCode},
     response::{Html, IntoResponse, Response},
 };
-use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use thiserror::Error;
-use validator::Validate;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::csrf::CsrfToken;
+use crate::sessions::ActiveSession;
 
 pub struct HtmlTemplate<T>(pub T);
 
@@ -31,129 +36,348 @@ where
 #[template(path = "account.html")]
 pub struct AccountTemplate {
     pub title: String,
+    pub csrf_token: String,
     pub email: String,
+    pub totp_enabled: bool,
+}
+
+#[derive(Template)]
+#[template(path = "deactivate.html")]
+pub struct DeactivateTemplate {
+    pub title: String,
+    pub csrf_token: String,
+    pub errors: FieldErrors,
+}
+
+#[derive(Template)]
+#[template(path = "delete.html")]
+pub struct DeleteAccountTemplate {
+    pub title: String,
+    pub csrf_token: String,
+    pub errors: FieldErrors,
 }
 
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct IndexTemplate {
     pub title: String,
+    pub csrf_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "error.html")]
+pub struct ErrorTemplate {
+    pub title: String,
+    pub message: String,
+    pub csrf_token: String,
+}
+
+/// A message for pages that have nothing else to say, e.g. "Check your email".
+#[derive(Template)]
+#[template(path = "notice.html")]
+pub struct NoticeTemplate {
+    pub title: String,
+    pub message: String,
+    pub csrf_token: String,
 }
 
 #[derive(Template)]
 #[template(path = "login.html")]
 pub struct LoginTemplate {
     pub title: String,
-    pub errors: validator::ValidationErrors,
+    pub csrf_token: String,
+    pub email: String,
+    pub errors: FieldErrors,
+    /// Why logging in doesn't work right now, after too many failures.
+    pub lockout: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "login_2fa.html")]
+pub struct TwoFactorLoginTemplate {
+    pub title: String,
+    pub csrf_token: String,
+    pub errors: FieldErrors,
+}
+
+#[derive(Template)]
+#[template(path = "recovery_codes.html")]
+pub struct RecoveryCodesTemplate {
+    pub title: String,
+    pub csrf_token: String,
+    pub codes: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "sessions.html")]
+pub struct SessionsTemplate {
+    pub title: String,
+    pub csrf_token: String,
+    pub sessions: Vec<ActiveSession>,
 }
 
 #[derive(Template)]
 #[template(path = "signup.html")]
 pub struct SignupTemplate {
     pub title: String,
-    pub errors: validator::ValidationErrors,
+    pub csrf_token: String,
+    pub email: String,
+    pub errors: FieldErrors,
+}
+
+/// Two-factor authentication settings. Unless it's `enabled`, the page enrolls the QR code of
+/// `secret`.
+#[derive(Template)]
+#[template(path = "two_factor.html")]
+pub struct TwoFactorTemplate {
+    pub title: String,
+    pub csrf_token: String,
+    pub enabled: bool,
+    pub qr_code: String,
+    pub secret: String,
+    pub errors: FieldErrors,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct ValidatedSignupForm<T>(pub T);
+/// Error messages by form field, rendered next to its input.
+#[derive(Debug, Default)]
+pub struct FieldErrors(HashMap<String, Vec<String>>);
+
+impl FieldErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: &str, message: &str) {
+        self.0
+            .entry(field.to_string())
+            .or_default()
+            .push(message.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn has(&self, field: &str) -> bool {
+        self.0.contains_key(field)
+    }
+
+    pub fn get(&self, field: &str) -> &[String] {
+        self.0.get(field).map_or(&[], Vec::as_slice)
+    }
+}
+
+impl From<ValidationErrors> for FieldErrors {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields = Self::new();
+        for (field, errors) in errors.field_errors() {
+            for error in errors {
+                // Struct-level validators report under `__all__`, so their errors are coded with
+                // the field they're about instead.
+                let field = if field == "__all__" {
+                    error.code.as_ref()
+                } else {
+                    field
+                };
+                let message = error.message.as_deref().unwrap_or("is invalid");
+                fields.add(field, message);
+            }
+        }
+        fields
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[validate(schema(function = "validate_confirm_password", skip_on_field_errors = false))]
+pub struct CreateSignupInput {
+    #[validate(email(message = "Enter a valid email address."))]
+    pub email: String,
+    #[validate(length(min = 6, message = "Use at least 6 characters."))]
+    pub password: String,
+    pub confirm_password: String,
+}
+
+fn validate_confirm_password(input: &CreateSignupInput) -> Result<(), ValidationError> {
+    if input.password == input.confirm_password {
+        return Ok(());
+    }
+
+    let mut error = ValidationError::new("confirm_password");
+    error.message = Some(Cow::from("Passwords do not match."));
+    Err(error)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateLoginInput {
+    #[validate(email(message = "Enter a valid email address."))]
+    pub email: String,
+    #[validate(length(min = 6, message = "Use at least 6 characters."))]
+    pub password: String,
+}
+
+/// A code of an authenticator app, or a recovery code when logging in.
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCodeInput {
+    pub code: String,
+}
+
+/// The password, re-entered to confirm deactivating or deleting the account.
+#[derive(Debug, Deserialize)]
+pub struct PasswordConfirmationInput {
+    pub password: String,
+}
+
+/// The session to log out on the sessions page.
+#[derive(Debug, Deserialize)]
+pub struct RevokeSessionInput {
+    pub id: String,
+}
+
+pub struct ValidatedSignupForm(pub CreateSignupInput);
 
 #[async_trait]
-impl<T, S, B> FromRequest<S, B> for ValidatedSignupForm<T>
+impl<S, B> FromRequest<S, B> for ValidatedSignupForm
 where
-    T: DeserializeOwned + Validate,
     S: Send + Sync,
-    Form<T>: FromRequest<S, B, Rejection = FormRejection>,
+    Form<CreateSignupInput>: FromRequest<S, B, Rejection = FormRejection>,
     B: Send + 'static,
 {
     type Rejection = SignupFormError;
 
     async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
-        let Form(value) = Form::<T>::from_request(req, state).await?;
-        value.validate()?;
-        Ok(ValidatedSignupForm(value))
+        let csrf_token = CsrfToken::from_extensions(req.extensions());
+        let Form(input) = Form::<CreateSignupInput>::from_request(req, state)
+            .await
+            .map_err(|e| SignupFormError::AxumFormRejection(csrf_token.clone(), e))?;
+        match input.validate() {
+            Ok(()) => Ok(ValidatedSignupForm(input)),
+            Err(errors) => Err(SignupFormError::ValidationError(csrf_token, input, errors)),
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct ValidatedLoginForm<T>(pub T);
+pub struct ValidatedLoginForm(pub CreateLoginInput);
 
 #[async_trait]
-impl<T, S, B> FromRequest<S, B> for ValidatedLoginForm<T>
+impl<S, B> FromRequest<S, B> for ValidatedLoginForm
 where
-    T: DeserializeOwned + Validate,
     S: Send + Sync,
-    Form<T>: FromRequest<S, B, Rejection = FormRejection>,
+    Form<CreateLoginInput>: FromRequest<S, B, Rejection = FormRejection>,
     B: Send + 'static,
 {
     type Rejection = LoginFormError;
 
     async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
-        let Form(value) = Form::<T>::from_request(req, state).await?;
-        value.validate()?;
-        Ok(ValidatedLoginForm(value))
+        let csrf_token = CsrfToken::from_extensions(req.extensions());
+        let Form(input) = Form::<CreateLoginInput>::from_request(req, state)
+            .await
+            .map_err(|e| LoginFormError::AxumFormRejection(csrf_token.clone(), e))?;
+        match input.validate() {
+            Ok(()) => Ok(ValidatedLoginForm(input)),
+            Err(errors) => Err(LoginFormError::ValidationError(csrf_token, input, errors)),
+        }
     }
 }
 
 #[derive(Debug, Error)]
 pub enum SignupFormError {
-    #[error(transparent)]
-    ValidationError(#[from] validator::ValidationErrors),
+    #[error("{2}")]
+    ValidationError(CsrfToken, CreateSignupInput, ValidationErrors),
 
-    #[error(transparent)]
-    AxumFormRejection(#[from] FormRejection),
+    #[error("{1}")]
+    AxumFormRejection(CsrfToken, #[source] FormRejection),
 }
 
 impl IntoResponse for SignupFormError {
     fn into_response(self) -> Response {
-        match self {
-            SignupFormError::ValidationError(v) => {
-                let template = SignupTemplate {
-                    title: "App - Signup|Error".to_string(),
-                    errors: v,
-                };
-                (StatusCode::BAD_REQUEST, HtmlTemplate(template))
+        let (csrf_token, email, errors) = match self {
+            SignupFormError::ValidationError(csrf_token, input, errors) => {
+                (csrf_token, input.email, errors.into())
             }
-            SignupFormError::AxumFormRejection(_) => {
-                let empty_errors = validator::ValidationErrors::new();
-                let template = SignupTemplate {
-                    title: "App - Signup|Error".to_string(),
-                    errors: empty_errors,
-                };
-                (StatusCode::BAD_REQUEST, HtmlTemplate(template))
+            SignupFormError::AxumFormRejection(csrf_token, _) => {
+                (csrf_token, String::new(), FieldErrors::new())
             }
-        }
-        .into_response()
+        };
+        let template = SignupTemplate {
+            title: "App - Signup|Error".to_string(),
+            csrf_token: csrf_token.0,
+            email,
+            errors,
+        };
+        (StatusCode::BAD_REQUEST, HtmlTemplate(template)).into_response()
     }
 }
 
 #[derive(Debug, Error)]
 pub enum LoginFormError {
-    #[error(transparent)]
-    ValidationError(#[from] validator::ValidationErrors),
+    #[error("{2}")]
+    ValidationError(CsrfToken, CreateLoginInput, ValidationErrors),
 
-    #[error(transparent)]
-    AxumFormRejection(#[from] FormRejection),
+    #[error("{1}")]
+    AxumFormRejection(CsrfToken, #[source] FormRejection),
 }
 
 impl IntoResponse for LoginFormError {
     fn into_response(self) -> Response {
-        match self {
-            LoginFormError::ValidationError(v) => {
-                let template = LoginTemplate {
-                    title: "App - Login|Error".to_string(),
-                    errors: v,
-                };
-                (StatusCode::BAD_REQUEST, HtmlTemplate(template))
+        let (csrf_token, email, errors) = match self {
+            LoginFormError::ValidationError(csrf_token, input, errors) => {
+                (csrf_token, input.email, errors.into())
             }
-            LoginFormError::AxumFormRejection(_) => {
-                let empty_errors = validator::ValidationErrors::new();
-                let template = LoginTemplate {
-                    title: "App - Login|Error".to_string(),
-                    errors: empty_errors,
-                };
-                (StatusCode::BAD_REQUEST, HtmlTemplate(template))
+            LoginFormError::AxumFormRejection(csrf_token, _) => {
+                (csrf_token, String::new(), FieldErrors::new())
             }
+        };
+        let template = LoginTemplate {
+            title: "App - Login|Error".to_string(),
+            csrf_token: csrf_token.0,
+            email,
+            errors,
+            lockout: None,
+        };
+        (StatusCode::BAD_REQUEST, HtmlTemplate(template)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signup(email: &str, password: &str, confirm_password: &str) -> CreateSignupInput {
+        CreateSignupInput {
+            email: email.to_string(),
+            password: password.to_string(),
+            confirm_password: confirm_password.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_signup_validation() {
+        assert!(signup("john@example.com", "password123", "password123")
+            .validate()
+            .is_ok());
+
+        let errors = FieldErrors::from(signup("john", "pass", "password").validate().unwrap_err());
+        assert_eq!(errors.get("email"), ["Enter a valid email address."]);
+        assert_eq!(errors.get("password"), ["Use at least 6 characters."]);
+        assert_eq!(errors.get("confirm_password"), ["Passwords do not match."]);
+    }
+
+    #[test]
+    fn test_render_field_errors() {
+        let mut errors = FieldErrors::new();
+        errors.add("email", "An account with this email already exists.");
+        let html = SignupTemplate {
+            title: "App - Signup|Error".to_string(),
+            csrf_token: "token".to_string(),
+            email: "john@example.com".to_string(),
+            errors,
         }
-        .into_response()
+        .render()
+        .unwrap();
+
+        assert!(html.contains(r#"value="john@example.com""#));
+        assert!(html.contains(r#"<input type="hidden" name="csrf_token" value="token">"#));
+        assert!(html.contains("An account with this email already exists."));
+        assert_eq!(html.matches("is-invalid").count(), 1);
     }
 }
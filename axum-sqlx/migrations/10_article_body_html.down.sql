@@ -0,0 +1,4 @@
+alter table article
+    drop column body_html,
+    drop column body_html_source_hash,
+    drop column toc;
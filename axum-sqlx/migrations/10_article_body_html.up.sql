@@ -0,0 +1,7 @@
+-- `body` rendered from Markdown by the API when an article is created or updated, along with a hash of
+-- the `body` it was rendered from. Articles written by other means (e.g. `axum-sqlx-admin seed`), or
+-- whose hash doesn't match their `body` anymore, are rendered whenever they are read.
+alter table article
+    add column body_html             text,
+    add column body_html_source_hash bytea,
+    add column toc                   jsonb;
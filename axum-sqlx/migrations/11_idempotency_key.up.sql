@@ -0,0 +1,16 @@
+-- Responses to requests sent with an `Idempotency-Key` header, replayed when a client retries them.
+-- Keys expire after 24 hours.
+create table idempotency_key
+(
+    idempotency_key  text primary key,
+    -- SHA-256 of the method, URI, `Authorization` header and body, to detect a key being reused for
+    -- a different request.
+    request_hash     bytea       not null,
+    -- These are null while the first request is still being processed.
+    response_status  int2,
+    response_headers jsonb,
+    response_body    bytea,
+    created_at       timestamptz not null default now()
+);
+
+create index on idempotency_key (created_at);
@@ -0,0 +1,4 @@
+drop table user_token;
+
+alter table "user"
+    drop column email_verified_at;
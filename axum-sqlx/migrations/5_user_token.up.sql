@@ -0,0 +1,21 @@
+alter table "user"
+    add column email_verified_at timestamptz;
+
+-- Single-use tokens mailed to users to verify their email or reset their password.
+-- Only a SHA-256 hash of the token is stored, so a leaked table can't be used to take over accounts.
+create table user_token
+(
+    token_hash bytea primary key,
+    user_id    uuid        not null references "user" (user_id) on delete cascade,
+    purpose    text        not null,
+    expires_at timestamptz not null,
+    used_at    timestamptz,
+    created_at timestamptz not null default now(),
+    updated_at timestamptz,
+
+    constraint user_token_purpose check (purpose in ('verify_email', 'reset_password'))
+);
+
+select trigger_updated_at('user_token');
+
+create index on user_token (user_id, purpose);
@@ -0,0 +1,3 @@
+drop table user_mute;
+
+drop table user_block;
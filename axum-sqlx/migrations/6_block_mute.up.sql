@@ -0,0 +1,31 @@
+-- A user blocking another one hides the blocked user's articles, comments and profile from them,
+-- and forbids the blocked user from following them or commenting on their articles.
+create table user_block
+(
+    blocked_user_id  uuid        not null references "user" (user_id) on delete cascade,
+    blocking_user_id uuid        not null references "user" (user_id) on delete cascade,
+    created_at       timestamptz not null default now(),
+    updated_at       timestamptz,
+
+    constraint user_cannot_block_self check (blocked_user_id != blocking_user_id),
+    primary key (blocking_user_id, blocked_user_id)
+);
+
+select trigger_updated_at('user_block');
+
+-- For checking whether the author of an article or a profile blocked the current user.
+create index on user_block (blocked_user_id);
+
+-- Muting only hides the muted user's content; the muted user doesn't notice anything.
+create table user_mute
+(
+    muted_user_id  uuid        not null references "user" (user_id) on delete cascade,
+    muting_user_id uuid        not null references "user" (user_id) on delete cascade,
+    created_at     timestamptz not null default now(),
+    updated_at     timestamptz,
+
+    constraint user_cannot_mute_self check (muted_user_id != muting_user_id),
+    primary key (muting_user_id, muted_user_id)
+);
+
+select trigger_updated_at('user_mute');
@@ -0,0 +1,3 @@
+-- Disabled users can no longer log in or reset their password; see `axum-sqlx-admin user disable`.
+alter table "user"
+    add column disabled_at timestamptz;
@@ -0,0 +1,3 @@
+drop trigger notify_article_created on article;
+
+drop function notify_article_created();
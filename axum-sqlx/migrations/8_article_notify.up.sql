@@ -0,0 +1,20 @@
+-- Announce new articles on the `article_created` channel, so every server instance can push them to the
+-- WebSocket live feeds of the followers connected to it. Notifications are only delivered once the inserting
+-- transaction commits.
+create or replace function notify_article_created()
+    returns trigger as
+$$
+begin
+    perform pg_notify(
+        'article_created',
+        json_build_object('articleId', NEW.article_id, 'userId', NEW.user_id)::text
+    );
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger notify_article_created
+    after insert
+    on article
+    for each row
+execute procedure notify_article_created();
@@ -0,0 +1,3 @@
+drop table collection_article;
+
+drop table collection;
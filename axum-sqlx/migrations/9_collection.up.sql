@@ -0,0 +1,27 @@
+-- Private reading lists; unlike favorites, nobody but their owner can see them.
+create table collection
+(
+    collection_id uuid primary key default uuid_generate_v1mc(),
+    user_id       uuid not null references "user" (user_id) on delete cascade,
+    name          text collate "case_insensitive" not null,
+    created_at    timestamptz not null default now(),
+    updated_at    timestamptz not null default now(),
+
+    unique (user_id, name)
+);
+
+select trigger_updated_at('collection');
+
+create table collection_article
+(
+    collection_id uuid        not null references collection (collection_id) on delete cascade,
+    article_id    uuid        not null references article (article_id) on delete cascade,
+    -- Entries are listed by ascending position; gaps are fine.
+    position      int         not null,
+    created_at    timestamptz not null default now(),
+    updated_at    timestamptz,
+
+    primary key (collection_id, article_id)
+);
+
+select trigger_updated_at('collection_article');
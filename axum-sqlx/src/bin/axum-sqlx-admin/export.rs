@@ -0,0 +1,97 @@
+use anyhow::Context;
+use clap::Args;
+use sqlx::PgPool;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Write to this file instead of stdout.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+/// Dump the content of the database, referencing users by username and articles by slug.
+///
+/// Password hashes and tokens are left out.
+pub async fn run(db: &PgPool, args: ExportArgs) -> anyhow::Result<()> {
+    // Postgres builds the whole document; it only has to be pretty-printed here.
+    let export = sqlx::query_scalar!(
+        r#"
+            select json_build_object(
+                'users', (
+                    select coalesce(json_agg(json_build_object(
+                        'username', username,
+                        'email', email,
+                        'bio', bio,
+                        'image', image,
+                        'emailVerifiedAt', email_verified_at,
+                        'disabledAt', disabled_at,
+                        'createdAt', created_at
+                    ) order by created_at), '[]')
+                    from "user"
+                ),
+                'follows', (
+                    select coalesce(json_agg(json_build_object(
+                        'follower', follower.username,
+                        'followed', followed.username
+                    ) order by follow.created_at), '[]')
+                    from follow
+                    inner join "user" follower on follower.user_id = following_user_id
+                    inner join "user" followed on followed.user_id = followed_user_id
+                ),
+                'articles', (
+                    select coalesce(json_agg(json_build_object(
+                        'slug', slug,
+                        'title', title,
+                        'description', description,
+                        'body', body,
+                        'tagList', tag_list,
+                        'author', author.username,
+                        'createdAt', article.created_at,
+                        'updatedAt', article.updated_at
+                    ) order by article.created_at), '[]')
+                    from article
+                    inner join "user" author using (user_id)
+                ),
+                'favorites', (
+                    select coalesce(json_agg(json_build_object(
+                        'slug', slug,
+                        'username', username
+                    ) order by fav.created_at), '[]')
+                    from article_favorite fav
+                    inner join article using (article_id)
+                    inner join "user" on "user".user_id = fav.user_id
+                ),
+                'comments', (
+                    select coalesce(json_agg(json_build_object(
+                        'slug', slug,
+                        'author', username,
+                        'body', comment.body,
+                        'createdAt', comment.created_at
+                    ) order by comment.created_at), '[]')
+                    from article_comment comment
+                    inner join article using (article_id)
+                    inner join "user" on "user".user_id = comment.user_id
+                )
+            )::text "export!"
+        "#
+    )
+    .fetch_one(db)
+    .await?;
+
+    let export: serde_json::Value = serde_json::from_str(&export)?;
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => {
+            Box::new(File::create(path).with_context(|| format!("Failed to create {:?}", path))?)
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+
+    serde_json::to_writer_pretty(&mut out, &export)?;
+    writeln!(out)?;
+
+    Ok(())
+}
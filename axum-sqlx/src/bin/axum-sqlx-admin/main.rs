@@ -0,0 +1,58 @@
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use sqlx::postgres::PgPoolOptions;
+
+use axum_sqlx::config::Config;
+
+mod export;
+mod migrate;
+mod seed;
+mod user;
+
+/// Maintenance tasks for the axum-sqlx server.
+///
+/// Reads the same environment (and `.env` file) as the server.
+#[derive(Parser)]
+#[command(name = "axum-sqlx-admin", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply, revert or list database migrations.
+    #[command(subcommand)]
+    Migrate(migrate::MigrateCommand),
+    /// Generate users, follows, articles and favorites for load testing.
+    Seed(seed::SeedArgs),
+    /// Manage user accounts.
+    #[command(subcommand)]
+    User(user::UserCommand),
+    /// Dump users, follows, articles, favorites and comments as JSON.
+    Export(export::ExportArgs),
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // read .env file
+    dotenvy::dotenv().ok();
+
+    let cli = Cli::parse();
+
+    let config = envy::from_env::<Config>().context("Failed to parse environment")?;
+
+    // No `statement_timeout` here, unlike the server: seeding a large dataset can take a while.
+    let db = PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&config.database_url)
+        .await
+        .context("Failed to connect to the database")?;
+
+    match cli.command {
+        Command::Migrate(command) => migrate::run(&db, command).await,
+        Command::Seed(args) => seed::run(&db, args).await,
+        Command::User(command) => user::run(&db, command).await,
+        Command::Export(args) => export::run(&db, args).await,
+    }
+}
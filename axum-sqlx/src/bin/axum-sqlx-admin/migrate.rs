@@ -0,0 +1,100 @@
+use anyhow::{bail, Context};
+use clap::Subcommand;
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// The same migrations the server applies on start, embedded at compile time.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Apply all pending migrations.
+    Up,
+    /// Revert applied migrations, which requires them to have a `.down.sql` script.
+    Down {
+        /// Revert every migration newer than this version; by default only the latest one.
+        #[arg(long)]
+        target: Option<i64>,
+    },
+    /// List migrations and whether they have been applied.
+    Status,
+}
+
+pub async fn run(db: &PgPool, command: MigrateCommand) -> anyhow::Result<()> {
+    match command {
+        MigrateCommand::Up => {
+            MIGRATOR
+                .run(db)
+                .await
+                .context("Failed to apply migrations")?;
+            println!("database is up to date");
+        }
+        MigrateCommand::Down { target } => {
+            let applied = applied_versions(db).await?;
+
+            let Some(&latest) = applied.keys().max() else {
+                bail!("no migrations have been applied");
+            };
+            let target = target.unwrap_or(latest - 1);
+
+            // `Migrator::undo` silently skips migrations without a down script, so refuse
+            // instead of pretending to have reverted them.
+            for &version in applied.keys().filter(|&&version| version > target) {
+                let reversible = MIGRATOR
+                    .iter()
+                    .any(|m| m.version == version && m.migration_type.is_down_migration());
+
+                if !reversible {
+                    bail!(
+                        "migration {} has no down script and can't be reverted automatically",
+                        version
+                    );
+                }
+            }
+
+            MIGRATOR
+                .undo(db, target)
+                .await
+                .context("Failed to revert migrations")?;
+            println!("reverted migrations newer than {}", target);
+        }
+        MigrateCommand::Status => {
+            let applied = applied_versions(db).await?;
+
+            for migration in MIGRATOR
+                .iter()
+                .filter(|m| !m.migration_type.is_down_migration())
+            {
+                let status = match applied.get(&migration.version) {
+                    None => "pending",
+                    Some(checksum) if checksum[..] != migration.checksum[..] => {
+                        "applied (changed since)"
+                    }
+                    Some(_) => "applied",
+                };
+
+                println!(
+                    "{:>4}  {:<24}  {}",
+                    migration.version, migration.description, status
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checksums of the applied migrations by version.
+async fn applied_versions(db: &PgPool) -> anyhow::Result<HashMap<i64, Vec<u8>>> {
+    let mut conn = db.acquire().await?;
+
+    conn.ensure_migrations_table().await?;
+
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum.into_owned()))
+        .collect())
+}
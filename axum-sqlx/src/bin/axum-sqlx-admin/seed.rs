@@ -0,0 +1,217 @@
+use clap::Args;
+use rand::rngs::StdRng;
+use rand::seq::{index, SliceRandom};
+use rand::{Rng, SeedableRng};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use axum_sqlx::http::hash_password;
+
+const TAGS: &[&str] = &[
+    "rust",
+    "axum",
+    "sqlx",
+    "postgres",
+    "tokio",
+    "web",
+    "async",
+    "testing",
+    "performance",
+    "design",
+];
+
+const WORDS: &[&str] = &[
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+    "sed",
+    "do",
+    "eiusmod",
+    "tempor",
+    "incididunt",
+    "labore",
+    "magna",
+    "aliqua",
+];
+
+#[derive(Args)]
+pub struct SeedArgs {
+    /// Number of users to create.
+    #[arg(long, default_value_t = 100)]
+    users: usize,
+    /// Articles written by each user.
+    #[arg(long, default_value_t = 5)]
+    articles_per_user: usize,
+    /// Other users each user follows.
+    #[arg(long, default_value_t = 10)]
+    follows_per_user: usize,
+    /// Articles each user favorites.
+    #[arg(long, default_value_t = 20)]
+    favorites_per_user: usize,
+    /// The password of every generated user.
+    #[arg(long, default_value = "password123")]
+    password: String,
+}
+
+/// Insert the generated rows in one transaction, each table with a single `unnest` insert.
+///
+/// Usernames carry a random run id, so seeding can be repeated against the same database.
+pub async fn run(db: &PgPool, args: SeedArgs) -> anyhow::Result<()> {
+    let mut rng = StdRng::from_entropy();
+    let run_id = format!("{:06x}", rng.gen::<u32>() & 0xff_ffff);
+
+    // Hashing is deliberately slow, so every generated user shares the same hash.
+    let password_hash = hash_password(args.password.clone()).await?;
+
+    let usernames: Vec<String> = (0..args.users)
+        .map(|i| format!("seed_{}_{}", run_id, i))
+        .collect();
+    let emails: Vec<String> = usernames
+        .iter()
+        .map(|username| format!("{}@example.com", username))
+        .collect();
+
+    let mut tx = db.begin().await?;
+
+    let user_ids: Vec<Uuid> = sqlx::query_scalar!(
+        r#"
+            insert into "user" (username, email, password_hash, email_verified_at)
+            select username, email, $3, now()
+            from unnest($1::text[], $2::text[]) as u(username, email)
+            returning user_id
+        "#,
+        &usernames,
+        &emails,
+        password_hash,
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    let mut following = Vec::new();
+    let mut followed = Vec::new();
+    let others = user_ids.len().saturating_sub(1);
+    for (i, &user_id) in user_ids.iter().enumerate() {
+        for j in index::sample(&mut rng, others, args.follows_per_user.min(others)) {
+            // skip over the user themselves
+            let j = if j >= i { j + 1 } else { j };
+            following.push(user_id);
+            followed.push(user_ids[j]);
+        }
+    }
+
+    sqlx::query!(
+        r#"
+            insert into follow (following_user_id, followed_user_id)
+            select * from unnest($1::uuid[], $2::uuid[])
+        "#,
+        &following,
+        &followed,
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let mut authors = Vec::new();
+    let mut slugs = Vec::new();
+    let mut titles = Vec::new();
+    let mut descriptions = Vec::new();
+    let mut bodies = Vec::new();
+    let mut tag_lists = Vec::new();
+    for (i, &user_id) in user_ids.iter().enumerate() {
+        for j in 0..args.articles_per_user {
+            let title = capitalize(&sentence(&mut rng, 3..7));
+
+            authors.push(user_id);
+            slugs.push(format!("seed-{}-{}-{}", run_id, i, j));
+            titles.push(title);
+            descriptions.push(capitalize(&sentence(&mut rng, 6..12)));
+            bodies.push(
+                (0..rng.gen_range(2..6))
+                    .map(|_| capitalize(&sentence(&mut rng, 20..60)) + ".")
+                    .collect::<Vec<_>>()
+                    .join("\n\n"),
+            );
+
+            let tag_count = rng.gen_range(1..4);
+            let mut tags: Vec<_> = TAGS.choose_multiple(&mut rng, tag_count).copied().collect();
+            // `create_article` stores tags sorted, too.
+            tags.sort_unstable();
+            tag_lists.push(tags.join(","));
+        }
+    }
+
+    let article_ids: Vec<Uuid> = sqlx::query_scalar!(
+        r#"
+            insert into article (user_id, slug, title, description, body, tag_list)
+            select user_id, slug, title, description, body, string_to_array(tags, ',')
+            from unnest($1::uuid[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[])
+                as a(user_id, slug, title, description, body, tags)
+            returning article_id
+        "#,
+        &authors,
+        &slugs,
+        &titles,
+        &descriptions,
+        &bodies,
+        &tag_lists,
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    let mut favorite_users = Vec::new();
+    let mut favorite_articles = Vec::new();
+    for &user_id in &user_ids {
+        let count = args.favorites_per_user.min(article_ids.len());
+        for j in index::sample(&mut rng, article_ids.len(), count) {
+            favorite_users.push(user_id);
+            favorite_articles.push(article_ids[j]);
+        }
+    }
+
+    sqlx::query!(
+        r#"
+            insert into article_favorite (user_id, article_id)
+            select * from unnest($1::uuid[], $2::uuid[])
+        "#,
+        &favorite_users,
+        &favorite_articles,
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    println!(
+        "seeded {} users named seed_{}_*, {} follows, {} articles and {} favorites",
+        user_ids.len(),
+        run_id,
+        following.len(),
+        article_ids.len(),
+        favorite_users.len(),
+    );
+    println!("every user's password is {:?}", args.password);
+
+    Ok(())
+}
+
+fn sentence(rng: &mut impl Rng, words: std::ops::Range<usize>) -> String {
+    let len = rng.gen_range(words);
+
+    (0..len)
+        .map(|_| *WORDS.choose(rng).expect("WORDS is not empty"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::{bail, Context};
+use clap::Subcommand;
+use rand::distributions::{Alphanumeric, DistString};
+use sqlx::PgPool;
+
+use axum_sqlx::http::hash_password;
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Create a user whose email counts as verified.
+    Create {
+        #[arg(long)]
+        username: String,
+        #[arg(long)]
+        email: String,
+        /// Generated and printed if not given.
+        #[arg(long, env = "ADMIN_USER_PASSWORD")]
+        password: Option<String>,
+    },
+    /// Stop a user from logging in, resetting their password or using the tokens issued to them.
+    Disable { username: String },
+    /// Allow a disabled user to log in again.
+    Enable { username: String },
+    /// Set a new password for a user.
+    ResetPassword {
+        username: String,
+        /// Generated and printed if not given.
+        #[arg(long, env = "ADMIN_USER_PASSWORD")]
+        password: Option<String>,
+    },
+}
+
+pub async fn run(db: &PgPool, command: UserCommand) -> anyhow::Result<()> {
+    match command {
+        UserCommand::Create {
+            username,
+            email,
+            password,
+        } => {
+            let (password, generated) = password_or_generate(password);
+            let password_hash = hash_password(password.clone()).await?;
+
+            let res = sqlx::query!(
+                r#"
+                    insert into "user" (username, email, password_hash, email_verified_at)
+                    values ($1, $2, $3, now())
+                "#,
+                username,
+                email,
+                password_hash,
+            )
+            .execute(db)
+            .await;
+
+            if let Err(sqlx::Error::Database(e)) = &res {
+                match e.constraint() {
+                    Some("user_username_key") => bail!("username {} is taken", username),
+                    Some("user_email_key") => bail!("email {} is taken", email),
+                    _ => (),
+                }
+            }
+            res.context("Failed to create user")?;
+
+            println!("created user {}", username);
+            if generated {
+                println!("password: {}", password);
+            }
+        }
+        UserCommand::Disable { username } => {
+            let mut tx = db.begin().await?;
+
+            let user_id = sqlx::query_scalar!(
+                r#"
+                    update "user"
+                    set disabled_at = coalesce(disabled_at, now())
+                    where username = $1
+                    returning user_id
+                "#,
+                username,
+            )
+            .fetch_optional(&mut tx)
+            .await?
+            .with_context(|| format!("no user named {}", username))?;
+
+            // Outstanding verification and reset tokens shouldn't outlive the account.
+            sqlx::query!(
+                r#"update user_token set used_at = now() where user_id = $1 and used_at is null"#,
+                user_id,
+            )
+            .execute(&mut tx)
+            .await?;
+
+            tx.commit().await?;
+
+            println!("disabled user {}", username);
+        }
+        UserCommand::Enable { username } => {
+            let res = sqlx::query!(
+                r#"update "user" set disabled_at = null where username = $1"#,
+                username,
+            )
+            .execute(db)
+            .await?;
+
+            if res.rows_affected() == 0 {
+                bail!("no user named {}", username);
+            }
+
+            println!("enabled user {}", username);
+        }
+        UserCommand::ResetPassword { username, password } => {
+            let (password, generated) = password_or_generate(password);
+            let password_hash = hash_password(password.clone()).await?;
+
+            let res = sqlx::query!(
+                r#"update "user" set password_hash = $1 where username = $2"#,
+                password_hash,
+                username,
+            )
+            .execute(db)
+            .await?;
+
+            if res.rows_affected() == 0 {
+                bail!("no user named {}", username);
+            }
+
+            println!("reset the password of {}", username);
+            if generated {
+                println!("password: {}", password);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn password_or_generate(password: Option<String>) -> (String, bool) {
+    match password {
+        Some(password) => (password, false),
+        None => (
+            Alphanumeric.sample_string(&mut rand::thread_rng(), 20),
+            true,
+        ),
+    }
+}
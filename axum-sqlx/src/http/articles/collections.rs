@@ -0,0 +1,344 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use futures::TryStreamExt;
+use sqlx::PgExecutor;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::http::articles::listing::MultipleArticlesBody;
+use crate::http::articles::markdown::TocJson;
+use crate::http::articles::ArticleFromQuery;
+use crate::http::error::{ConstraintKind, Error, ResultExt};
+use crate::http::extractor::AuthUser;
+use crate::http::types::Timestamptz;
+use crate::http::{query_span, ApiContext, Result};
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub(in crate::http) struct CollectionBody<T = Collection> {
+    collection: T,
+}
+
+#[derive(serde::Serialize)]
+pub(in crate::http) struct MultipleCollectionsBody {
+    collections: Vec<Collection>,
+}
+
+#[derive(serde::Deserialize)]
+pub(in crate::http) struct CreateCollection {
+    name: String,
+}
+
+/// The new order of a collection, which has to list each of its articles exactly once.
+#[derive(serde::Deserialize)]
+pub(in crate::http) struct ReorderCollection {
+    articles: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::http) struct Collection {
+    id: Uuid,
+    name: String,
+    articles_count: i64,
+    created_at: Timestamptz,
+    updated_at: Timestamptz,
+}
+
+pub(in crate::http) async fn create_collection(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Json(req): Json<CollectionBody<CreateCollection>>,
+) -> Result<Json<CollectionBody>> {
+    let collection = sqlx::query_as!(
+        Collection,
+        r#"
+            insert into collection (user_id, name)
+            values ($1, $2)
+            returning
+                collection_id id,
+                name,
+                0::int8 "articles_count!",
+                created_at "created_at: Timestamptz",
+                updated_at "updated_at: Timestamptz"
+        "#,
+        auth_user.user_id,
+        req.collection.name,
+    )
+    .fetch_one(&ctx.db)
+    .instrument(query_span("insert collection"))
+    .await
+    .on_constraint("collection_user_id_name_key", |_| {
+        Error::unprocessable_entity([("name", "you already have a collection with this name")])
+    })
+    .on_constraint_kind(ConstraintKind::ForeignKey, |_| Error::Unauthorized)?;
+
+    Ok(Json(CollectionBody { collection }))
+}
+
+pub(in crate::http) async fn list_collections(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+) -> Result<Json<MultipleCollectionsBody>> {
+    let collections = sqlx::query_as!(
+        Collection,
+        r#"
+            select
+                collection_id id,
+                name,
+                coalesce(
+                    (select count(*) from collection_article entry where entry.collection_id = collection.collection_id),
+                    0
+                ) "articles_count!",
+                created_at "created_at: Timestamptz",
+                updated_at "updated_at: Timestamptz"
+            from collection
+            where user_id = $1
+            order by name
+        "#,
+        auth_user.user_id,
+    )
+    .fetch_all(&ctx.db)
+    .instrument(query_span("list collections"))
+    .await?;
+
+    Ok(Json(MultipleCollectionsBody { collections }))
+}
+
+pub(in crate::http) async fn delete_collection(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(collection_id): Path<Uuid>,
+) -> Result<StatusCode> {
+    let mut tx = ctx.db.begin().await?;
+
+    check_own_collection(&mut tx, collection_id, auth_user.user_id).await?;
+
+    sqlx::query!(
+        r#"delete from collection where collection_id = $1"#,
+        collection_id
+    )
+    .execute(&mut tx)
+    .instrument(query_span("delete collection"))
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+pub(in crate::http) async fn get_collection_articles(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(collection_id): Path<Uuid>,
+) -> Result<Json<MultipleArticlesBody>> {
+    check_own_collection(&ctx.db, collection_id, auth_user.user_id).await?;
+
+    collection_articles(&ctx.db, collection_id, auth_user.user_id)
+        .await
+        .map(Json)
+}
+
+/// Append an article to the collection; adding it again keeps its position.
+pub(in crate::http) async fn add_collection_article(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path((collection_id, slug)): Path<(Uuid, String)>,
+) -> Result<Json<MultipleArticlesBody>> {
+    let mut tx = ctx.db.begin().await?;
+
+    check_own_collection(&mut tx, collection_id, auth_user.user_id).await?;
+
+    let article_id = sqlx::query_scalar!(r#"select article_id from article where slug = $1"#, slug)
+        .fetch_optional(&mut tx)
+        .instrument(query_span("select article id"))
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    sqlx::query!(
+        r#"
+            insert into collection_article (collection_id, article_id, position)
+            select $1, $2, coalesce(max(position) + 1, 0)
+            from collection_article
+            where collection_id = $1
+            on conflict do nothing
+        "#,
+        collection_id,
+        article_id,
+    )
+    .execute(&mut tx)
+    .instrument(query_span("insert collection article"))
+    .await?;
+
+    let articles = collection_articles(&mut tx, collection_id, auth_user.user_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(articles))
+}
+
+pub(in crate::http) async fn remove_collection_article(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path((collection_id, slug)): Path<(Uuid, String)>,
+) -> Result<Json<MultipleArticlesBody>> {
+    let mut tx = ctx.db.begin().await?;
+
+    check_own_collection(&mut tx, collection_id, auth_user.user_id).await?;
+
+    sqlx::query!(
+        r#"
+            delete from collection_article entry
+            using article
+            where entry.article_id = article.article_id
+            and entry.collection_id = $1 and article.slug = $2
+        "#,
+        collection_id,
+        slug,
+    )
+    .execute(&mut tx)
+    .instrument(query_span("delete collection article"))
+    .await?;
+
+    let articles = collection_articles(&mut tx, collection_id, auth_user.user_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(articles))
+}
+
+pub(in crate::http) async fn reorder_collection(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(collection_id): Path<Uuid>,
+    Json(req): Json<ReorderCollection>,
+) -> Result<Json<MultipleArticlesBody>> {
+    let mut tx = ctx.db.begin().await?;
+
+    check_own_collection(&mut tx, collection_id, auth_user.user_id).await?;
+
+    let mut current = sqlx::query_scalar!(
+        r#"
+            select slug
+            from collection_article
+            inner join article using (article_id)
+            where collection_id = $1
+        "#,
+        collection_id,
+    )
+    .fetch_all(&mut tx)
+    .instrument(query_span("select collection slugs"))
+    .await?;
+
+    let mut requested = req.articles.clone();
+    current.sort();
+    requested.sort();
+
+    if current != requested {
+        return Err(Error::unprocessable_entity([(
+            "articles",
+            "must list every article of the collection exactly once",
+        )]));
+    }
+
+    sqlx::query!(
+        r#"
+            update collection_article entry
+            set position = new_order.position - 1
+            from unnest($2::text[]) with ordinality new_order(slug, position)
+            inner join article using (slug)
+            where entry.collection_id = $1 and entry.article_id = article.article_id
+        "#,
+        collection_id,
+        &req.articles[..],
+    )
+    .execute(&mut tx)
+    .instrument(query_span("update collection positions"))
+    .await?;
+
+    let articles = collection_articles(&mut tx, collection_id, auth_user.user_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(articles))
+}
+
+/// Collections are private: anyone but their owner gets `403 Forbidden`.
+///
+/// This locks the collection until the end of the transaction, if any, so that concurrent changes
+/// to its entries don't interleave.
+async fn check_own_collection(
+    e: impl PgExecutor<'_>,
+    collection_id: Uuid,
+    user_id: Uuid,
+) -> Result<()> {
+    let owner_id = sqlx::query_scalar!(
+        r#"select user_id from collection where collection_id = $1 for update"#,
+        collection_id
+    )
+    .fetch_optional(e)
+    .instrument(query_span("select collection owner"))
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if owner_id != user_id {
+        return Err(Error::Forbidden);
+    }
+
+    Ok(())
+}
+
+async fn collection_articles(
+    e: impl PgExecutor<'_>,
+    collection_id: Uuid,
+    user_id: Uuid,
+) -> Result<MultipleArticlesBody> {
+    let articles: Vec<_> = sqlx::query_as!(
+        ArticleFromQuery,
+        r#"
+            select
+                slug,
+                title,
+                description,
+                body,
+                body_html,
+                body_html_source_hash,
+                toc "toc: TocJson",
+                tag_list,
+                article.created_at "created_at: Timestamptz",
+                article.updated_at "updated_at: Timestamptz",
+                exists(
+                    select 1 from article_favorite fav
+                    where fav.article_id = article.article_id and fav.user_id = $2
+                ) "favorited!",
+                coalesce(
+                    (select count(*) from article_favorite fav where fav.article_id = article.article_id),
+                    0
+                ) "favorites_count!",
+                author.username author_username,
+                author.bio author_bio,
+                author.image author_image,
+                exists(
+                    select 1 from follow
+                    where followed_user_id = author.user_id and following_user_id = $2
+                ) "following_author!"
+            from collection_article entry
+            inner join article using (article_id)
+            inner join "user" author on author.user_id = article.user_id
+            where entry.collection_id = $1
+            order by entry.position, entry.created_at
+        "#,
+        collection_id,
+        user_id,
+    )
+    .fetch(e)
+    .map_ok(ArticleFromQuery::into_article)
+    .try_collect()
+    .instrument(query_span("list collection articles"))
+    .await?;
+
+    Ok(MultipleArticlesBody {
+        articles_count: articles.len(),
+        articles,
+    })
+}
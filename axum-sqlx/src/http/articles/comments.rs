@@ -0,0 +1,215 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use futures::TryStreamExt;
+use tracing::Instrument;
+
+use crate::http::articles::markdown;
+use crate::http::error::{ConstraintKind, Error, ResultExt};
+use crate::http::extractor::{AuthUser, MaybeAuthUser};
+use crate::http::profiles::{is_blocked, Profile};
+use crate::http::types::Timestamptz;
+use crate::http::{query_span, ApiContext, Result};
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub(in crate::http) struct CommentBody<T = Comment> {
+    comment: T,
+}
+
+#[derive(serde::Serialize)]
+pub(in crate::http) struct MultipleCommentsBody {
+    comments: Vec<Comment>,
+}
+
+#[derive(serde::Deserialize)]
+pub(in crate::http) struct AddComment {
+    body: String,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::http) struct Comment {
+    id: i64,
+    created_at: Timestamptz,
+    updated_at: Timestamptz,
+    body: String,
+    /// `body` rendered from Markdown to sanitized HTML.
+    body_html: String,
+    author: Profile,
+}
+
+struct CommentFromQuery {
+    comment_id: i64,
+    created_at: Timestamptz,
+    updated_at: Timestamptz,
+    body: String,
+    author_username: String,
+    author_bio: String,
+    author_image: Option<String>,
+    following_author: bool,
+}
+
+impl CommentFromQuery {
+    fn into_comment(self) -> Comment {
+        Comment {
+            id: self.comment_id,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            body_html: markdown::render_html(&self.body),
+            body: self.body,
+            author: Profile {
+                username: self.author_username,
+                bio: self.author_bio,
+                image: self.author_image,
+                following: self.following_author,
+            },
+        }
+    }
+}
+
+pub(in crate::http) async fn get_article_comments(
+    maybe_auth_user: MaybeAuthUser,
+    ctx: State<ApiContext>,
+    Path(slug): Path<String>,
+) -> Result<Json<MultipleCommentsBody>> {
+    let article_id = sqlx::query_scalar!(r#"select article_id from article where slug = $1"#, slug)
+        .fetch_optional(&ctx.db)
+        .instrument(query_span("select article id"))
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    let comments: Vec<_> = sqlx::query_as!(
+        CommentFromQuery,
+        r#"
+            select
+                comment_id,
+                comment.created_at "created_at: Timestamptz",
+                comment.updated_at "updated_at: Timestamptz",
+                comment.body,
+                author.username author_username,
+                author.bio author_bio,
+                author.image author_image,
+                exists(
+                    select 1 from follow
+                    where followed_user_id = author.user_id and following_user_id = $2
+                ) "following_author!"
+            from article_comment comment
+            inner join "user" author using (user_id)
+            where article_id = $1
+            -- authors the viewer blocked or muted are hidden from them
+            and not exists(
+                select 1 from user_block
+                where blocked_user_id = author.user_id and blocking_user_id = $2
+            )
+            and not exists(
+                select 1 from user_mute
+                where muted_user_id = author.user_id and muting_user_id = $2
+            )
+            order by comment.created_at
+        "#,
+        article_id,
+        maybe_auth_user.user_id(),
+    )
+    .fetch(&ctx.db)
+    .map_ok(CommentFromQuery::into_comment)
+    .try_collect()
+    .instrument(query_span("list comments"))
+    .await?;
+
+    Ok(Json(MultipleCommentsBody { comments }))
+}
+
+/// Users blocked by the author of the article may not comment on it.
+pub(in crate::http) async fn add_comment(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(slug): Path<String>,
+    Json(req): Json<CommentBody<AddComment>>,
+) -> Result<Json<CommentBody>> {
+    let mut tx = ctx.db.begin().await?;
+
+    let article = sqlx::query!(
+        r#"select article_id, user_id from article where slug = $1"#,
+        slug
+    )
+    .fetch_optional(&mut tx)
+    .instrument(query_span("select article id"))
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if is_blocked(&mut tx, article.user_id, auth_user.user_id).await? {
+        return Err(Error::Forbidden);
+    }
+
+    let comment = sqlx::query_as!(
+        CommentFromQuery,
+        r#"
+            with inserted_comment as (
+                insert into article_comment (article_id, user_id, body)
+                values ($1, $2, $3)
+                returning comment_id, user_id, body, created_at, updated_at
+            )
+
+            select
+                comment_id,
+                comment.created_at "created_at: Timestamptz",
+                comment.updated_at "updated_at: Timestamptz",
+                comment.body,
+                author.username author_username,
+                author.bio author_bio,
+                author.image author_image,
+                -- user is forbidden to follow themselves
+                false "following_author!"
+            from inserted_comment comment
+            inner join "user" author using (user_id)
+        "#,
+        article.article_id,
+        auth_user.user_id,
+        req.comment.body,
+    )
+    .fetch_one(&mut tx)
+    .instrument(query_span("insert comment"))
+    .await
+    .on_constraint_kind(ConstraintKind::ForeignKey, |_| Error::Unauthorized)?;
+
+    tx.commit().await?;
+
+    Ok(Json(CommentBody {
+        comment: comment.into_comment(),
+    }))
+}
+
+pub(in crate::http) async fn delete_comment(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path((slug, comment_id)): Path<(String, i64)>,
+) -> Result<StatusCode> {
+    let comment = sqlx::query!(
+        r#"
+            select comment.user_id
+            from article_comment comment
+            inner join article using (article_id)
+            where slug = $1 and comment_id = $2
+        "#,
+        slug,
+        comment_id,
+    )
+    .fetch_optional(&ctx.db)
+    .instrument(query_span("select comment"))
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if comment.user_id != auth_user.user_id {
+        return Err(Error::Forbidden);
+    }
+
+    sqlx::query!(
+        r#"delete from article_comment where comment_id = $1"#,
+        comment_id
+    )
+    .execute(&ctx.db)
+    .instrument(query_span("delete comment"))
+    .await?;
+
+    Ok(StatusCode::OK)
+}
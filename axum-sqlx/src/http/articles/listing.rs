@@ -1,18 +1,20 @@
 use axum::extract::{Query, State};
 use axum::Json;
 use futures::TryStreamExt;
+use tracing::Instrument;
 
+use crate::http::articles::markdown::TocJson;
 use crate::http::articles::{Article, ArticleFromQuery};
 use crate::http::extractor::{AuthUser, MaybeAuthUser};
 use crate::http::types::Timestamptz;
-use crate::http::ApiContext;
 use crate::http::Result;
+use crate::http::{query_span, ApiContext};
 
 #[derive(serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MultipleArticlesBody {
-    articles: Vec<Article>,
-    articles_count: usize,
+    pub(in crate::http) articles: Vec<Article>,
+    pub(in crate::http) articles_count: usize,
 }
 
 #[derive(serde::Deserialize, Default)]
@@ -46,6 +48,9 @@ pub(in crate::http) async fn list_articles(
                 title,
                 description,
                 body,
+                body_html,
+                body_html_source_hash,
+                toc "toc: TocJson",
                 tag_list,
                 article.created_at "created_at: Timestamptz",
                 article.updated_at "updated_at: Timestamptz",
@@ -71,6 +76,15 @@ pub(in crate::http) async fn list_articles(
                     where username = $4
                 )
             )
+            -- authors the viewer blocked or muted are hidden from them
+            and not exists(
+                select 1 from user_block
+                where blocked_user_id = author.user_id and blocking_user_id = $1
+            )
+            and not exists(
+                select 1 from user_mute
+                where muted_user_id = author.user_id and muting_user_id = $1
+            )
             order by article.created_at desc
             limit $5
             offset $6
@@ -85,6 +99,7 @@ pub(in crate::http) async fn list_articles(
     .fetch(&ctx.db)
     .map_ok(ArticleFromQuery::into_article)
     .try_collect()
+    .instrument(query_span("list articles"))
     .await?;
 
     Ok(Json(MultipleArticlesBody {
@@ -107,6 +122,9 @@ pub(in crate::http) async fn feed_articles(
                 title,
                 description,
                 body,
+                body_html,
+                body_html_source_hash,
+                toc "toc: TocJson",
                 tag_list,
                 article.created_at "created_at: Timestamptz",
                 article.updated_at "updated_at: Timestamptz",
@@ -123,6 +141,15 @@ pub(in crate::http) async fn feed_articles(
             inner join article on followed_user_id = article.user_id
             inner join "user" author using (user_id)
             where following_user_id = $1
+            -- authors the viewer blocked or muted are hidden from them
+            and not exists(
+                select 1 from user_block
+                where blocked_user_id = author.user_id and blocking_user_id = $1
+            )
+            and not exists(
+                select 1 from user_mute
+                where muted_user_id = author.user_id and muting_user_id = $1
+            )
             limit $2
             offset $3
         "#,
@@ -133,6 +160,7 @@ pub(in crate::http) async fn feed_articles(
     .fetch(&ctx.db)
     .map_ok(ArticleFromQuery::into_article)
     .try_collect()
+    .instrument(query_span("feed articles"))
     .await?;
 
     Ok(Json(MultipleArticlesBody {
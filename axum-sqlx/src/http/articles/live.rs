@@ -0,0 +1,197 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::http::articles::markdown::TocJson;
+use crate::http::articles::{ArticleBody, ArticleFromQuery};
+use crate::http::extractor::AuthUser;
+use crate::http::types::Timestamptz;
+use crate::http::{query_span, ApiContext, Result};
+
+/// The channel `migrations/8_article_notify.up.sql` notifies on every article insert.
+const CHANNEL: &str = "article_created";
+
+/// Articles created on any server instance, fanned out to the live feeds connected to this one.
+#[derive(Clone)]
+pub(in crate::http) struct ArticleEvents(broadcast::Sender<ArticleCreated>);
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::http) struct ArticleCreated {
+    article_id: Uuid,
+    user_id: Uuid,
+}
+
+impl ArticleEvents {
+    /// Spawn a task forwarding `article_created` notifications until the pool is closed.
+    ///
+    /// This has to be called from within a Tokio runtime.
+    pub(in crate::http) fn listen(db: PgPool) -> Self {
+        // Only slow feeds lag behind by this many articles; they skip the oldest ones.
+        let (tx, _) = broadcast::channel(256);
+
+        tokio::spawn(forward_notifications(db, tx.clone()));
+
+        Self(tx)
+    }
+}
+
+async fn forward_notifications(db: PgPool, tx: broadcast::Sender<ArticleCreated>) {
+    // The listener holds on to a connection of the pool, which would make `PgPool::close()` wait
+    // forever.
+    let mut close_event = db.close_event();
+
+    let _ = close_event
+        .do_until(async {
+            loop {
+                if let Err(e) = listen(&db, &tx).await {
+                    // The close event may be noticed only after the listener failed because of it.
+                    if db.is_closed() {
+                        break;
+                    }
+
+                    tracing::warn!(error = ?e, "live feed listener failed, restarting");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        })
+        .await;
+}
+
+async fn listen(db: &PgPool, tx: &broadcast::Sender<ArticleCreated>) -> anyhow::Result<()> {
+    let mut listener = PgListener::connect_with(db).await?;
+    listener.listen(CHANNEL).await?;
+
+    loop {
+        // This reconnects by itself if the connection is lost, although notifications sent
+        // in the meantime are missed.
+        let notification = listener.recv().await?;
+
+        match serde_json::from_str(notification.payload()) {
+            // It's fine if no feed is connected right now.
+            Ok(event) => {
+                let _ = tx.send(event);
+            }
+            Err(e) => tracing::error!(error = ?e, "invalid {} payload", CHANNEL),
+        }
+    }
+}
+
+/// Upgrade to a WebSocket pushing new articles of the users the current user follows, as
+/// `{"article": {...}}` text messages in the same format as the other article endpoints.
+pub(in crate::http) async fn live_feed(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    // Subscribe before upgrading so articles published during the handshake aren't missed.
+    let events = ctx.article_events.0.subscribe();
+
+    ws.on_upgrade(move |socket| push_articles(ctx.0, auth_user, events, socket))
+}
+
+async fn push_articles(
+    ctx: ApiContext,
+    auth_user: AuthUser,
+    mut events: broadcast::Receiver<ArticleCreated>,
+    mut socket: WebSocket,
+) {
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "live feed lagged behind");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                if event.user_id == auth_user.user_id {
+                    continue;
+                }
+
+                let article = match followed_article(&ctx, auth_user.user_id, event.article_id).await {
+                    Ok(Some(article)) => article,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        tracing::error!(error = ?e, "failed to load article for live feed");
+                        continue;
+                    }
+                };
+
+                let message = serde_json::to_string(&ArticleBody {
+                    article: article.into_article(),
+                })
+                .expect("articles always serialize");
+
+                if socket.send(Message::Text(message)).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => match message {
+                // Pings are answered by the WebSocket implementation, anything else is ignored.
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => (),
+            },
+        }
+    }
+}
+
+/// The article, if it belongs in the feed of `user_id`: its author is followed and not muted.
+async fn followed_article(
+    ctx: &ApiContext,
+    user_id: Uuid,
+    article_id: Uuid,
+) -> Result<Option<ArticleFromQuery>> {
+    let article = sqlx::query_as!(
+        ArticleFromQuery,
+        r#"
+            select
+                slug,
+                title,
+                description,
+                body,
+                body_html,
+                body_html_source_hash,
+                toc "toc: TocJson",
+                tag_list,
+                article.created_at "created_at: Timestamptz",
+                article.updated_at "updated_at: Timestamptz",
+                exists(
+                    select 1 from article_favorite fav
+                    where fav.article_id = article.article_id and fav.user_id = $1
+                ) "favorited!",
+                coalesce(
+                    (select count(*) from article_favorite fav where fav.article_id = article.article_id),
+                    0
+                ) "favorites_count!",
+                author.username author_username,
+                author.bio author_bio,
+                author.image author_image,
+                true "following_author!"
+            from follow
+            inner join article on followed_user_id = article.user_id
+            inner join "user" author using (user_id)
+            where following_user_id = $1 and article_id = $2
+            and not exists(
+                select 1 from user_mute
+                where muted_user_id = author.user_id and muting_user_id = $1
+            )
+        "#,
+        user_id,
+        article_id,
+    )
+    .fetch_optional(&ctx.db)
+    .instrument(query_span("select live feed article"))
+    .await?;
+
+    Ok(article)
+}
@@ -0,0 +1,172 @@
+use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::http::articles::slugify;
+
+/// Prepended by the sanitizer to every `id` in the HTML, so that ids of headings can't clash with
+/// the ones of the page embedding the article.
+const ID_PREFIX: &str = "user-content-";
+
+/// Average silent reading speed used for `readingTimeMinutes`.
+const WORDS_PER_MINUTE: usize = 200;
+
+pub(in crate::http) type Toc = Vec<TocEntry>;
+
+/// How the table of contents is cached in `article.toc`.
+pub(in crate::http) type TocJson = sqlx::types::Json<Toc>;
+
+/// A heading of an article, linking to it with `#{id}`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+pub(in crate::http) struct TocEntry {
+    level: u8,
+    text: String,
+    id: String,
+}
+
+pub(in crate::http) struct Rendered {
+    pub html: String,
+    pub toc: Toc,
+}
+
+/// Render Markdown to sanitized HTML, giving every heading an `id` listed in the table of contents.
+pub(in crate::http) fn render(markdown: &str) -> Rendered {
+    let mut events = Vec::new();
+    let mut toc = Vec::new();
+    let mut seen_ids = HashMap::new();
+    // The level and text of the heading being parsed, and where it starts in `events`.
+    let mut heading: Option<(HeadingLevel, String, usize)> = None;
+
+    for event in Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    ) {
+        match (&event, &mut heading) {
+            (Event::Start(Tag::Heading(level, ..)), None) => {
+                heading = Some((*level, String::new(), events.len()));
+            }
+            (Event::Text(text) | Event::Code(text), Some((_, heading_text, _))) => {
+                heading_text.push_str(text);
+            }
+            (Event::End(Tag::Heading(..)), Some(_)) => {
+                let (level, text, start) = heading.take().expect("matched Some");
+                let id = unique_id(&mut seen_ids, &text);
+                let level = level as u8;
+
+                // `Tag::Heading` can only borrow its id from the source, so write the tags by hand.
+                events[start] = Event::Html(format!(r#"<h{} id="{}">"#, level, id).into());
+                events.push(Event::Html(format!("</h{}>\n", level).into()));
+
+                toc.push(TocEntry {
+                    level,
+                    text,
+                    id: format!("{}{}", ID_PREFIX, id),
+                });
+                continue;
+            }
+            _ => (),
+        }
+
+        events.push(event);
+    }
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events.into_iter());
+
+    Rendered {
+        html: sanitize(&unsafe_html),
+        toc,
+    }
+}
+
+/// Render Markdown to sanitized HTML.
+pub(in crate::http) fn render_html(markdown: &str) -> String {
+    render(markdown).html
+}
+
+/// A SHA-256 hash of `markdown`, stored along with the HTML rendered from it to tell whether
+/// that is still up to date.
+pub(in crate::http) fn source_hash(markdown: &str) -> Vec<u8> {
+    Sha256::digest(markdown.as_bytes()).to_vec()
+}
+
+/// The estimated time to read `markdown`, rounded up to whole minutes.
+pub(in crate::http) fn reading_time_minutes(markdown: &str) -> i64 {
+    let words = markdown.split_whitespace().count();
+
+    words.div_ceil(WORDS_PER_MINUTE).max(1) as i64
+}
+
+fn sanitize(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_tag_attributes("h1", ["id"])
+        .add_tag_attributes("h2", ["id"])
+        .add_tag_attributes("h3", ["id"])
+        .add_tag_attributes("h4", ["id"])
+        .add_tag_attributes("h5", ["id"])
+        .add_tag_attributes("h6", ["id"])
+        .id_prefix(Some(ID_PREFIX))
+        .clean(html)
+        .to_string()
+}
+
+/// Slugify a heading, appending `-1`, `-2`, ... to repeated ones.
+fn unique_id(seen_ids: &mut HashMap<String, usize>, text: &str) -> String {
+    let mut id = slugify(text);
+    if id.is_empty() {
+        id.push_str("section");
+    }
+
+    let count = seen_ids.entry(id.clone()).or_insert(0);
+    *count += 1;
+
+    match *count {
+        1 => id,
+        n => format!("{}-{}", id, n - 1),
+    }
+}
+
+#[test]
+fn test_render() {
+    let rendered = render(
+        "# Dragons\n\nThey *fly*.\n\n## `Fire` breathing\n\n## Dragons\n\n<script>alert(1)</script>\n\n[link](javascript:alert(1))",
+    );
+
+    assert_eq!(
+        rendered.html,
+        "<h1 id=\"user-content-dragons\">Dragons</h1>\n\
+         <p>They <em>fly</em>.</p>\n\
+         <h2 id=\"user-content-fire-breathing\"><code>Fire</code> breathing</h2>\n\
+         <h2 id=\"user-content-dragons-1\">Dragons</h2>\n\
+         \n\
+         <p><a rel=\"noopener noreferrer\">link</a></p>\n"
+    );
+
+    assert_eq!(
+        rendered.toc,
+        [
+            TocEntry {
+                level: 1,
+                text: "Dragons".into(),
+                id: "user-content-dragons".into()
+            },
+            TocEntry {
+                level: 2,
+                text: "Fire breathing".into(),
+                id: "user-content-fire-breathing".into()
+            },
+            TocEntry {
+                level: 2,
+                text: "Dragons".into(),
+                id: "user-content-dragons-1".into()
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_reading_time_minutes() {
+    assert_eq!(reading_time_minutes(""), 1);
+    assert_eq!(reading_time_minutes(&"word ".repeat(200)), 1);
+    assert_eq!(reading_time_minutes(&"word ".repeat(201)), 2);
+}
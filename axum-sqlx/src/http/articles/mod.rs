@@ -1,18 +1,27 @@
 use axum::extract::{Path, State};
-use axum::routing::{get, post};
+use axum::middleware;
+use axum::routing::{delete, get, post};
 use axum::{Json, Router};
 use itertools::Itertools;
+use tracing::Instrument;
 // use sqlx::{Executor, Postgres};
 // use uuid::Uuid;
 
-use crate::http::error::{Error, ResultExt};
-use crate::http::extractor::AuthUser; // , MaybeAuthUser};
+use crate::http::articles::markdown::{Toc, TocJson};
+use crate::http::caching;
+use crate::http::error::{ConstraintKind, Error, ResultExt};
+use crate::http::extractor::{AuthUser, MaybeAuthUser};
 use crate::http::profiles::Profile;
 use crate::http::types::Timestamptz;
-use crate::http::{ApiContext, Result};
+use crate::http::{query_span, ApiContext, Result};
 
-// mod comments;
+mod collections;
+mod comments;
 mod listing;
+mod live;
+mod markdown;
+
+pub(in crate::http) use live::ArticleEvents;
 
 pub(crate) fn router() -> Router<ApiContext> {
     Router::new()
@@ -21,6 +30,34 @@ pub(crate) fn router() -> Router<ApiContext> {
             post(create_article).get(listing::list_articles),
         )
         .route("/api/articles/feed", get(listing::feed_articles))
+        .route("/api/articles/feed/live", get(live::live_feed))
+        .route("/api/articles/:slug", get(get_article).put(update_article))
+        .route(
+            "/api/articles/:slug/comments",
+            post(comments::add_comment).get(comments::get_article_comments),
+        )
+        .route(
+            "/api/articles/:slug/comments/:comment_id",
+            delete(comments::delete_comment),
+        )
+        .route(
+            "/api/collections",
+            post(collections::create_collection).get(collections::list_collections),
+        )
+        .route(
+            "/api/collections/:collection_id",
+            delete(collections::delete_collection),
+        )
+        .route(
+            "/api/collections/:collection_id/articles",
+            get(collections::get_collection_articles).put(collections::reorder_collection),
+        )
+        .route(
+            "/api/collections/:collection_id/articles/:slug",
+            post(collections::add_collection_article)
+                .delete(collections::remove_collection_article),
+        )
+        .route_layer(middleware::from_fn(caching::conditional_get))
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -35,6 +72,11 @@ struct Article {
     title: String,
     description: String,
     body: String,
+    /// `body` rendered from Markdown to sanitized HTML.
+    body_html: String,
+    /// The headings of `body_html`.
+    toc: Toc,
+    reading_time_minutes: i64,
     tag_list: Vec<String>,
     created_at: Timestamptz,
     updated_at: Timestamptz,
@@ -48,6 +90,10 @@ struct ArticleFromQuery {
     title: String,
     description: String,
     body: String,
+    body_html: Option<String>,
+    /// What `body_html` was rendered from; see `markdown::source_hash()`.
+    body_html_source_hash: Option<Vec<u8>>,
+    toc: Option<TocJson>,
     tag_list: Vec<String>,
     created_at: Timestamptz,
     updated_at: Timestamptz,
@@ -61,11 +107,27 @@ struct ArticleFromQuery {
 
 impl ArticleFromQuery {
     fn into_article(self) -> Article {
+        let (body_html, toc) = match (self.body_html, self.toc, self.body_html_source_hash) {
+            (Some(body_html), Some(toc), Some(source_hash))
+                if source_hash == markdown::source_hash(&self.body) =>
+            {
+                (body_html, toc.0)
+            }
+            // Not rendered by the API, or `body` was changed without refreshing the cache.
+            _ => {
+                let rendered = markdown::render(&self.body);
+                (rendered.html, rendered.toc)
+            }
+        };
+
         Article {
             slug: self.slug,
             title: self.title,
             description: self.description,
+            reading_time_minutes: markdown::reading_time_minutes(&self.body),
             body: self.body,
+            body_html,
+            toc,
             tag_list: self.tag_list,
             created_at: self.created_at,
             updated_at: self.updated_at,
@@ -90,6 +152,15 @@ struct CreateArticle {
     tag_list: Vec<String>,
 }
 
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateArticle {
+    title: Option<String>,
+    description: Option<String>,
+    body: Option<String>,
+    tag_list: Option<Vec<String>>,
+}
+
 async fn create_article(
     auth_user: AuthUser,
     ctx: State<ApiContext>,
@@ -97,18 +168,24 @@ async fn create_article(
 ) -> Result<Json<ArticleBody>> {
     let slug = slugify(&req.article.title);
     req.article.tag_list.sort();
+    let rendered = markdown::render(&req.article.body);
 
     let article = sqlx::query_as!(
         ArticleFromQuery,
         r#"
             with inserted_article as (
-                insert into article (user_id, slug, title, description, body, tag_list)
-                values ($1, $2, $3, $4, $5, $6)
+                insert into article (
+                    user_id, slug, title, description, body, tag_list, body_html, body_html_source_hash, toc
+                )
+                values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
                 returning
                     slug,
                     title,
                     description,
                     body,
+                    body_html,
+                    body_html_source_hash,
+                    toc "toc: TocJson",
                     tag_list,
                     -- This is how you can override the inferred type of a column.
                     created_at "created_at: Timestamptz",
@@ -133,13 +210,184 @@ async fn create_article(
         req.article.description,
         req.article.body,
         &req.article.tag_list[..],
+        rendered.html,
+        markdown::source_hash(&req.article.body),
+        sqlx::types::Json(&rendered.toc) as _,
     )
     .fetch_one(&ctx.db)
+    .instrument(query_span("insert article"))
     .await
     .on_constraint("article_slug_key", |_| {
         Error::unprocessable_entity([("slug", format!("duplicate article slug: {}", slug))])
+    })
+    // The token is still valid but its user has been deleted since.
+    .on_constraint_kind(ConstraintKind::ForeignKey, |_| Error::Unauthorized)?;
+
+    Ok(Json(ArticleBody {
+        article: article.into_article(),
+    }))
+}
+
+async fn get_article(
+    maybe_auth_user: MaybeAuthUser,
+    ctx: State<ApiContext>,
+    Path(slug): Path<String>,
+) -> Result<Json<ArticleBody>> {
+    let article = sqlx::query_as!(
+        ArticleFromQuery,
+        r#"
+            select
+                slug,
+                title,
+                description,
+                body,
+                body_html,
+                body_html_source_hash,
+                toc "toc: TocJson",
+                tag_list,
+                article.created_at "created_at: Timestamptz",
+                article.updated_at "updated_at: Timestamptz",
+                exists(
+                    select 1 from article_favorite fav
+                    where fav.article_id = article.article_id and fav.user_id = $2
+                ) "favorited!",
+                coalesce(
+                    (select count(*) from article_favorite fav where fav.article_id = article.article_id),
+                    0
+                ) "favorites_count!",
+                author.username author_username,
+                author.bio author_bio,
+                author.image author_image,
+                exists(
+                    select 1 from follow
+                    where followed_user_id = author.user_id and following_user_id = $2
+                ) "following_author!"
+            from article
+            inner join "user" author using (user_id)
+            where slug = $1
+        "#,
+        slug,
+        maybe_auth_user.user_id(),
+    )
+    .fetch_optional(&ctx.db)
+    .instrument(query_span("select article"))
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    Ok(Json(ArticleBody {
+        article: article.into_article(),
+    }))
+}
+
+/// Only the author may update an article. Changing the title changes the slug as well.
+async fn update_article(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(slug): Path<String>,
+    Json(req): Json<ArticleBody<UpdateArticle>>,
+) -> Result<Json<ArticleBody>> {
+    let mut tx = ctx.db.begin().await?;
+
+    let article = sqlx::query!(
+        r#"select article_id, user_id from article where slug = $1 for update"#,
+        slug
+    )
+    .fetch_optional(&mut tx)
+    .instrument(query_span("select article id"))
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if article.user_id != auth_user.user_id {
+        return Err(Error::Forbidden);
+    }
+
+    let new_slug = req.article.title.as_deref().map(slugify);
+    let tag_list = req.article.tag_list.map(|mut tag_list| {
+        tag_list.sort();
+        tag_list
+    });
+    // Refresh the cached HTML along with the body.
+    let rendered = req.article.body.as_deref().map(markdown::render);
+
+    let article = sqlx::query_as!(
+        ArticleFromQuery,
+        r#"
+            with updated_article as (
+                update article
+                set
+                    slug = coalesce($2, slug),
+                    title = coalesce($3, title),
+                    description = coalesce($4, description),
+                    body = coalesce($5, body),
+                    tag_list = coalesce($6, tag_list),
+                    body_html = coalesce($7, body_html),
+                    body_html_source_hash = coalesce($8, body_html_source_hash),
+                    toc = coalesce($9, toc)
+                where article_id = $1
+                returning
+                    article_id,
+                    user_id,
+                    slug,
+                    title,
+                    description,
+                    body,
+                    body_html,
+                    body_html_source_hash,
+                    toc,
+                    tag_list,
+                    created_at,
+                    updated_at
+            )
+
+            select
+                slug,
+                title,
+                description,
+                body,
+                body_html,
+                body_html_source_hash,
+                toc "toc: TocJson",
+                tag_list,
+                article.created_at "created_at: Timestamptz",
+                article.updated_at "updated_at: Timestamptz",
+                exists(
+                    select 1 from article_favorite fav
+                    where fav.article_id = article.article_id and fav.user_id = author.user_id
+                ) "favorited!",
+                coalesce(
+                    (select count(*) from article_favorite fav where fav.article_id = article.article_id),
+                    0
+                ) "favorites_count!",
+                author.username author_username,
+                author.bio author_bio,
+                author.image author_image,
+                -- user is forbidden to follow themselves
+                false "following_author!"
+            from updated_article article
+            inner join "user" author using (user_id)
+        "#,
+        article.article_id,
+        new_slug,
+        req.article.title,
+        req.article.description,
+        req.article.body,
+        tag_list.as_deref(),
+        rendered.as_ref().map(|rendered| &rendered.html),
+        req.article.body.as_deref().map(markdown::source_hash),
+        rendered.as_ref().map(|rendered| sqlx::types::Json(&rendered.toc)) as _,
+    )
+    .fetch_one(&mut tx)
+    .instrument(query_span("update article"))
+    .await
+    .on_constraint("article_slug_key", |_| {
+        Error::unprocessable_entity([(
+            "slug",
+            format!("duplicate article slug: {}", new_slug.unwrap_or_default()),
+        )])
     })?;
 
+    tx.commit().await?;
+
     Ok(Json(ArticleBody {
         article: article.into_article(),
     }))
@@ -0,0 +1,90 @@
+use axum::body::{boxed, Body, Full};
+use axum::http::header::{AUTHORIZATION, CACHE_CONTROL, ETAG, IF_NONE_MATCH, VARY};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+
+/// Middleware adding conditional GET support to read-only JSON endpoints.
+///
+/// Successful `GET` responses get a weak `ETag` derived from the serialized body, which covers
+/// every article's `updatedAt` as well as counters such as `favoritesCount` that change without
+/// touching it. A request whose `If-None-Match` matches is answered with `304 Not Modified`.
+///
+/// Responses are `private` when the request was authenticated, since `MaybeAuthUser` then changes
+/// fields like `following` and `favorited`, and `public` otherwise. Either way clients have to
+/// revalidate before reusing them.
+pub(in crate::http) async fn conditional_get(req: Request<Body>, next: Next<Body>) -> Response {
+    if req.method() != Method::GET {
+        return next.run(req).await;
+    }
+
+    let authenticated = req.headers().contains_key(AUTHORIZATION);
+    let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+
+    let res = next.run(req).await;
+
+    if res.status() != StatusCode::OK {
+        return res;
+    }
+
+    let (mut parts, body) = res.into_parts();
+
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("failed to buffer response body: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let etag = weak_etag(&bytes);
+
+    parts.headers.insert(ETAG, etag.clone());
+    parts.headers.insert(
+        CACHE_CONTROL,
+        HeaderValue::from_static(if authenticated {
+            "private, no-cache"
+        } else {
+            "public, no-cache"
+        }),
+    );
+    parts
+        .headers
+        .append(VARY, HeaderValue::from_static("Authorization"));
+
+    if matches!(if_none_match, Some(value) if etag_matches(&value, &etag)) {
+        let mut headers = HeaderMap::new();
+        for name in [ETAG, CACHE_CONTROL, VARY] {
+            for value in parts.headers.get_all(&name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    Response::from_parts(parts, boxed(Full::from(bytes)))
+}
+
+fn weak_etag(body: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+
+    HeaderValue::try_from(format!("W/\"{}\"", hex)).expect("hex digits are a valid header value")
+}
+
+/// Weak comparison of `etag` against an `If-None-Match` header value, as required for
+/// `If-None-Match` by RFC 9110.
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let (Ok(if_none_match), Ok(etag)) = (if_none_match.to_str(), etag.to_str()) else {
+        return false;
+    };
+
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+
+    if_none_match
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || opaque(candidate) == etag)
+}
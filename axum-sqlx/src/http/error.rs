@@ -1,12 +1,15 @@
-use axum::http::header::WWW_AUTHENTICATE;
-use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::body::{boxed, Body, Full};
+use axum::http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, WWW_AUTHENTICATE};
+use axum::http::{HeaderMap, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use sqlx::error::DatabaseError;
-// use sqlx::error::DatabaseError;
 use std::borrow::Cow;
 use std::collections::HashMap;
 
+const PROBLEM_JSON: &str = "application/problem+json";
+
 /// A common error type that can be used throughout the API.
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -22,6 +25,14 @@ pub enum Error {
     #[error("resource path not found")]
     NotFound,
 
+    /// Return `409 Conflict`
+    #[error("the request conflicts with another one still in progress")]
+    Conflict,
+
+    /// Return `413 Payload Too Large`
+    #[error("the request body is too large")]
+    PayloadTooLarge,
+
     /// Return `422 Unprocessable Entity`
     #[error("error in the request body")]
     UnprocessableEntity {
@@ -57,16 +68,58 @@ impl Error {
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
             Self::Forbidden => StatusCode::FORBIDDEN,
             Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict => StatusCode::CONFLICT,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
             Self::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
             Self::Sqlx(_) | Self::Anyhow(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    /// A stable, machine-readable identifier of the error, for the `code` of problem details.
+    ///
+    /// Unlike the messages these are part of the API and must not change.
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::NotFound => "not_found",
+            Self::Conflict => "conflict",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::UnprocessableEntity { .. } => "validation_failed",
+            Self::Sqlx(_) => "database_error",
+            Self::Anyhow(_) => "internal_error",
+        }
+    }
+
+    fn problem(&self) -> Problem {
+        Problem {
+            code: self.code(),
+            status: self.status_code(),
+            detail: self.to_string(),
+            errors: match self {
+                Self::UnprocessableEntity { errors } => Some(errors.clone()),
+                _ => None,
+            },
+        }
+    }
 }
 
 /// Axum allows you to return `Result` from handler functions, but the error type
 /// also must be some sort of response type.
+///
+/// The body is in the RealWorld format; the details needed to render it as problem details instead
+/// are attached as a response extension for [`problem_details`] to pick up.
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
+        let problem = self.problem();
+        let mut res = self.into_realworld_response();
+        res.extensions_mut().insert(problem);
+        res
+    }
+}
+
+impl Error {
+    fn into_realworld_response(self) -> Response {
         match self {
             Self::UnprocessableEntity { errors } => {
                 #[derive(serde::Serialize)]
@@ -86,13 +139,13 @@ impl IntoResponse for Error {
                 )
                     .into_response();
             }
+            // These are emitted inside the request span, so exported traces carry the cause
+            // of the 500 alongside the route and user.
             Self::Sqlx(ref e) => {
-                // TODO
-                log::error!("SQLx error: {:?}", e);
+                tracing::error!(error = ?e, "SQLx error");
             }
             Self::Anyhow(ref e) => {
-                // TODO
-                log::error!("Generic error: {:?}", e);
+                tracing::error!(error = ?e, "Generic error");
             }
             // Other errors get mapped normally.
             _ => (),
@@ -102,6 +155,124 @@ impl IntoResponse for Error {
     }
 }
 
+/// An RFC 7807 problem details object describing an [`Error`].
+#[derive(Clone, Debug)]
+struct Problem {
+    code: &'static str,
+    status: StatusCode,
+    detail: String,
+    errors: Option<HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>>,
+}
+
+/// Middleware rendering [`Error`]s as `application/problem+json` for clients that ask for it in
+/// `Accept`, e.g.
+///
+/// ```json
+/// {
+///     "type": "about:blank",
+///     "title": "Unprocessable Entity",
+///     "status": 422,
+///     "detail": "error in the request body",
+///     "code": "validation_failed",
+///     "requestId": "0b5b3b4e-4c1a-4a4e-8d2b-5b1e9e1c2f3a",
+///     "errors": { "email": ["email taken"] }
+/// }
+/// ```
+///
+/// Everyone else keeps getting the RealWorld format. Headers of the original response, such as
+/// `WWW-Authenticate`, are preserved.
+pub(in crate::http) async fn problem_details(req: Request<Body>, next: Next<Body>) -> Response {
+    let accepts_problem = accepts_problem_json(req.headers());
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+
+    let mut res = next.run(req).await;
+
+    if !accepts_problem {
+        return res;
+    }
+
+    let Some(problem) = res.extensions_mut().remove::<Problem>() else {
+        return res;
+    };
+
+    let mut body = serde_json::json!({
+        "type": "about:blank",
+        "title": problem.status.canonical_reason(),
+        "status": problem.status.as_u16(),
+        "detail": problem.detail,
+        "code": problem.code,
+    });
+
+    if let Some(request_id) = request_id {
+        body["requestId"] = request_id.into();
+    }
+
+    if let Some(errors) = problem.errors {
+        body["errors"] = serde_json::json!(errors);
+    }
+
+    let (mut parts, _) = res.into_parts();
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Response::from_parts(parts, boxed(Full::from(body.to_string())))
+}
+
+/// Whether `Accept` lists `application/problem+json` with a non-zero quality.
+fn accepts_problem_json(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|range| {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default();
+
+            media_type.eq_ignore_ascii_case(PROBLEM_JSON)
+                && !params.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q == 0.0)
+                })
+        })
+}
+
+/// The kind of integrity constraint a database error violated, from its SQLSTATE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintKind {
+    /// `23502 not_null_violation`
+    NotNull,
+    /// `23503 foreign_key_violation`
+    ForeignKey,
+    /// `23505 unique_violation`
+    Unique,
+    /// `23514 check_violation`
+    Check,
+    /// `23P01 exclusion_violation`
+    Exclusion,
+}
+
+impl ConstraintKind {
+    fn of(error: &dyn DatabaseError) -> Option<Self> {
+        match error.code()?.as_ref() {
+            "23502" => Some(Self::NotNull),
+            "23503" => Some(Self::ForeignKey),
+            "23505" => Some(Self::Unique),
+            "23514" => Some(Self::Check),
+            "23P01" => Some(Self::Exclusion),
+            _ => None,
+        }
+    }
+}
+
 /// A little helper trait for more easily converting database constraint errors into API errors.
 ///
 /// ```rust,ignore
@@ -116,11 +287,21 @@ impl IntoResponse for Error {
 ///     .on_constraint("user_username_key", |_| Error::unprocessable_entity([("username", "already taken")]))?;
 /// ```
 pub trait ResultExt<T> {
+    /// Map a violation of the constraint called `name`, whether it's a unique index, a foreign
+    /// key or a check constraint such as `user_cannot_follow_self`.
     fn on_constraint(
         self,
         name: &str,
         f: impl FnOnce(Box<dyn DatabaseError>) -> Error,
     ) -> Result<T, Error>;
+
+    /// Map a violation of any constraint of the given kind, e.g. every foreign key of a table
+    /// without naming each of them.
+    fn on_constraint_kind(
+        self,
+        kind: ConstraintKind,
+        f: impl FnOnce(Box<dyn DatabaseError>) -> Error,
+    ) -> Result<T, Error>;
 }
 
 impl<T, E> ResultExt<T> for Result<T, E>
@@ -133,7 +314,22 @@ where
         map_err: impl FnOnce(Box<dyn DatabaseError>) -> Error,
     ) -> Result<T, Error> {
         self.map_err(|e| match e.into() {
-            Error::Sqlx(sqlx::Error::Database(dbe)) if dbe.constraint() == Some(name) => {
+            Error::Sqlx(sqlx::Error::Database(dbe))
+                if ConstraintKind::of(&*dbe).is_some() && dbe.constraint() == Some(name) =>
+            {
+                map_err(dbe)
+            }
+            e => e,
+        })
+    }
+
+    fn on_constraint_kind(
+        self,
+        kind: ConstraintKind,
+        map_err: impl FnOnce(Box<dyn DatabaseError>) -> Error,
+    ) -> Result<T, Error> {
+        self.map_err(|e| match e.into() {
+            Error::Sqlx(sqlx::Error::Database(dbe)) if ConstraintKind::of(&*dbe) == Some(kind) => {
                 map_err(dbe)
             }
             e => e,
@@ -1,16 +1,13 @@
 use async_trait::async_trait;
 use axum::extract::{FromRef, FromRequestParts};
 use axum::http::{header::AUTHORIZATION, request::Parts, HeaderValue};
-use hmac::{Hmac, Mac};
-use jwt::{SignWithKey, VerifyWithKey};
-use sha2::Sha384;
 use time::OffsetDateTime;
+use tracing::Instrument;
 use uuid::Uuid;
 
 use crate::http::error::Error;
-use crate::http::ApiContext;
+use crate::http::{query_span, ApiContext};
 
-const DEFAULT_SESSION_LENGTH: time::Duration = time::Duration::weeks(2);
 const SCHEME_PREFIX: &str = "Token ";
 
 pub struct AuthUser {
@@ -28,26 +25,26 @@ struct AuthUserClaims {
 
 impl AuthUser {
     pub(in crate::http) fn to_jwt(&self, ctx: &ApiContext) -> String {
-        let hmac = Hmac::<Sha384>::new_from_slice(ctx.config.hmac_key.as_bytes())
-            .expect("HMAC-SHA-384 can accept any key length");
+        let session_length = time::Duration::seconds(ctx.config.session_length_secs);
 
-        AuthUserClaims {
+        ctx.keyring.sign(&AuthUserClaims {
             user_id: self.user_id,
-            exp: (OffsetDateTime::now_utc() + DEFAULT_SESSION_LENGTH).unix_timestamp(),
-        }
-        .sign_with_key(&hmac)
-        .expect("HMAC signing should be infallible")
+            exp: (OffsetDateTime::now_utc() + session_length).unix_timestamp(),
+        })
     }
 
     /// Attempt to parse `Self` from an `Authorization` header.
-    fn from_authorization(ctx: &ApiContext, auth_header: &HeaderValue) -> Result<Self, Error> {
+    async fn from_authorization(
+        ctx: &ApiContext,
+        auth_header: &HeaderValue,
+    ) -> Result<Self, Error> {
         let auth_header = auth_header.to_str().map_err(|_| {
-            log::debug!("Authorization header is not valid UTF-8");
+            tracing::debug!("Authorization header is not valid UTF-8");
             Error::Unauthorized
         })?;
 
         if !auth_header.starts_with(SCHEME_PREFIX) {
-            log::debug!(
+            tracing::debug!(
                 "Authorization header does not start with '{}'",
                 SCHEME_PREFIX
             );
@@ -56,24 +53,27 @@ impl AuthUser {
 
         let token = &auth_header[SCHEME_PREFIX.len()..];
 
-        let jwt =
-            jwt::Token::<jwt::Header, AuthUserClaims, _>::parse_unverified(token).map_err(|e| {
-                log::debug!("JWT parsing failed: {}", e);
-                Error::Unauthorized
-            })?;
-
-        let hmac = Hmac::<Sha384>::new_from_slice(ctx.config.hmac_key.as_bytes())
-            .expect("HMAC-SHA-384 can accept any key length");
-
-        let jwt = jwt.verify_with_key(&hmac).map_err(|e| {
-            log::debug!("JWT verification failed: {}", e);
+        // This also rejects expired tokens.
+        let claims = ctx.keyring.verify::<AuthUserClaims>(token).map_err(|e| {
+            tracing::debug!("JWT verification failed: {}", e);
             Error::Unauthorized
         })?;
 
-        let (_header, claims) = jwt.into();
-
-        if claims.exp < OffsetDateTime::now_utc().unix_timestamp() {
-            log::debug!("JWT has expired");
+        // Extractors run inside the request span opened by the `TraceLayer`.
+        tracing::Span::current().record("user_id", tracing::field::display(claims.user_id));
+
+        // Tokens outlive disabling or deleting the user, so they don't count for much by themselves.
+        let active = sqlx::query_scalar!(
+            r#"select disabled_at is null "active!" from "user" where user_id = $1"#,
+            claims.user_id,
+        )
+        .fetch_optional(&ctx.db)
+        .instrument(query_span("select user disabled"))
+        .await?
+        .unwrap_or(false);
+
+        if !active {
+            tracing::debug!("user of JWT is disabled or deleted");
             return Err(Error::Unauthorized);
         }
 
@@ -106,7 +106,7 @@ where
             .get(AUTHORIZATION)
             .ok_or(Error::Unauthorized)?;
 
-        Self::from_authorization(&ctx, auth_header)
+        Self::from_authorization(&ctx, auth_header).await
     }
 }
 
@@ -121,13 +121,12 @@ where
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let ctx: ApiContext = ApiContext::from_ref(state);
 
-        Ok(Self(
-            // Get the value of the `Authorization` header, if it was sent at all.
-            parts
-                .headers
-                .get(AUTHORIZATION)
-                .map(|auth_header| AuthUser::from_authorization(&ctx, auth_header))
-                .transpose()?,
-        ))
+        // Get the value of the `Authorization` header, if it was sent at all.
+        match parts.headers.get(AUTHORIZATION) {
+            Some(auth_header) => Ok(Self(Some(
+                AuthUser::from_authorization(&ctx, auth_header).await?,
+            ))),
+            None => Ok(Self(None)),
+        }
     }
 }
@@ -0,0 +1,34 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use tracing::Instrument;
+
+use crate::http::{query_span, ApiContext};
+
+pub(crate) fn router() -> Router<ApiContext> {
+    Router::new()
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
+}
+
+/// Liveness probe: the process is up and able to serve requests.
+async fn live() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Readiness probe: the database is reachable, so requests can actually be handled.
+async fn ready(ctx: State<ApiContext>) -> StatusCode {
+    let res = sqlx::query("select 1")
+        .execute(&ctx.db)
+        .instrument(query_span("readiness check"))
+        .await;
+
+    match res {
+        Ok(_) => StatusCode::OK,
+        Err(e) => {
+            tracing::warn!("readiness check failed: {:?}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use axum::body::{boxed, Body, Full};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderName, HeaderValue, Method, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use http_body::{LengthLimitError, Limited};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+use tower::{Layer, Service, ServiceExt};
+use tracing::Instrument;
+
+use crate::http::{query_span, Error, Result};
+
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Set on responses replayed from an earlier request with the same key.
+const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+
+/// Keys are generated by clients, typically UUIDs; this only bounds what we store.
+const MAX_KEY_LEN: usize = 255;
+
+/// Request bodies are buffered to hash them, before the `Json` extractor could apply its default
+/// limit, so apply the same one here.
+const MAX_BODY_LEN: usize = 2 * 1024 * 1024;
+
+/// How `idempotency_key.response_headers` is stored.
+type StoredHeaders = sqlx::types::Json<Vec<(String, String)>>;
+
+/// Make `POST`s to the given paths safe to retry when they carry an `Idempotency-Key` header.
+///
+/// The first request with a key is processed normally, and a successful response is stored for
+/// 24 hours and replayed to any retry with the same key instead of processing it again. Reusing a
+/// key for a different request is rejected with `422 Unprocessable Entity`, and retrying while the
+/// first request is still in progress with `409 Conflict`. Error responses aren't stored, so
+/// requests that failed can be retried with the same key.
+#[derive(Clone)]
+pub(in crate::http) struct IdempotencyLayer {
+    db: PgPool,
+    paths: &'static [&'static str],
+}
+
+impl IdempotencyLayer {
+    pub(in crate::http) fn new(db: PgPool, paths: &'static [&'static str]) -> Self {
+        Self { db, paths }
+    }
+}
+
+impl<S> Layer<S> for IdempotencyLayer {
+    type Service = Idempotency<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Idempotency {
+            inner,
+            db: self.db.clone(),
+            paths: self.paths,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(in crate::http) struct Idempotency<S> {
+    inner: S,
+    db: PgPool,
+    paths: &'static [&'static str],
+}
+
+impl<S> Service<Request<Body>> for Idempotency<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // `inner` was polled ready, not its clone.
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+
+        let applies = req.method() == Method::POST
+            && self.paths.contains(&req.uri().path())
+            && req.headers().contains_key(IDEMPOTENCY_KEY);
+
+        if !applies {
+            return Box::pin(inner.oneshot(req));
+        }
+
+        let db = self.db.clone();
+
+        Box::pin(async move {
+            Ok(idempotent(db, inner, req)
+                .await
+                .unwrap_or_else(IntoResponse::into_response))
+        })
+    }
+}
+
+async fn idempotent<S>(db: PgPool, inner: S, req: Request<Body>) -> Result<Response>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible>,
+{
+    let key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|value| value.to_str().ok())
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+        .ok_or_else(|| {
+            Error::unprocessable_entity([(
+                "Idempotency-Key",
+                format!(
+                    "must be between 1 and {} visible ASCII characters",
+                    MAX_KEY_LEN
+                ),
+            )])
+        })?
+        .to_owned();
+
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(Limited::new(body, MAX_BODY_LEN))
+        .await
+        .map_err(|e| {
+            if e.is::<LengthLimitError>() {
+                Error::PayloadTooLarge
+            } else {
+                anyhow::anyhow!("failed to buffer request body: {}", e).into()
+            }
+        })?;
+
+    let request_hash = {
+        let mut hasher = Sha256::new();
+        for field in [
+            parts.method.as_str().as_bytes(),
+            parts.uri.to_string().as_bytes(),
+            parts
+                .headers
+                .get(AUTHORIZATION)
+                .map_or(&b""[..], HeaderValue::as_bytes),
+        ] {
+            // Length-prefixed so that fields can't run into each other.
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field);
+        }
+        hasher.update(&body);
+        hasher.finalize().to_vec()
+    };
+
+    sqlx::query!(r#"delete from idempotency_key where created_at < now() - interval '24 hours'"#)
+        .execute(&db)
+        .instrument(query_span("delete expired idempotency keys"))
+        .await?;
+
+    let inserted = sqlx::query!(
+        r#"
+            insert into idempotency_key (idempotency_key, request_hash)
+            values ($1, $2)
+            on conflict do nothing
+        "#,
+        key,
+        request_hash,
+    )
+    .execute(&db)
+    .instrument(query_span("insert idempotency key"))
+    .await?
+    .rows_affected()
+        == 1;
+
+    if !inserted {
+        return replay(&db, &key, &request_hash).await;
+    }
+
+    // From here on, the key must be released if the request doesn't succeed, including when the
+    // client hangs up and this future is dropped.
+    let mut pending = PendingKey {
+        db: db.clone(),
+        key: Some(key),
+    };
+
+    let res = match inner
+        .oneshot(Request::from_parts(parts, Body::from(body)))
+        .await
+    {
+        Ok(res) => res,
+        Err(infallible) => match infallible {},
+    };
+
+    if !res.status().is_success() {
+        pending.release().await?;
+        return Ok(res);
+    }
+
+    let (parts, body) = res.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to buffer response body: {}", e))?;
+
+    let headers: Vec<_> = parts
+        .headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+        .collect();
+
+    sqlx::query!(
+        r#"
+            update idempotency_key
+            set response_status = $2, response_headers = $3, response_body = $4
+            where idempotency_key = $1
+        "#,
+        pending.key.as_deref(),
+        parts.status.as_u16() as i16,
+        sqlx::types::Json(headers) as _,
+        &body[..],
+    )
+    .execute(&db)
+    .instrument(query_span("update idempotency key"))
+    .await?;
+
+    pending.key = None;
+
+    Ok(Response::from_parts(parts, boxed(Full::from(body))))
+}
+
+/// The stored response for a key that was already used.
+async fn replay(db: &PgPool, key: &str, request_hash: &[u8]) -> Result<Response> {
+    let stored = sqlx::query!(
+        r#"
+            select
+                request_hash,
+                response_status,
+                response_headers "response_headers: StoredHeaders",
+                response_body
+            from idempotency_key
+            where idempotency_key = $1
+        "#,
+        key,
+    )
+    .fetch_optional(db)
+    .instrument(query_span("select idempotency key"))
+    .await?
+    // The first request failed in the meantime and released the key.
+    .ok_or(Error::Conflict)?;
+
+    if stored.request_hash != request_hash {
+        return Err(Error::unprocessable_entity([(
+            "Idempotency-Key",
+            "was already used for a different request",
+        )]));
+    }
+
+    let (Some(status), Some(headers), Some(body)) = (
+        stored.response_status,
+        stored.response_headers,
+        stored.response_body,
+    ) else {
+        return Err(Error::Conflict);
+    };
+
+    let mut res = Response::new(boxed(Full::from(body)));
+    *res.status_mut() = StatusCode::from_u16(status as u16)
+        .map_err(|e| anyhow::anyhow!("invalid stored status {}: {}", status, e))?;
+
+    for (name, value) in headers.0 {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            res.headers_mut().append(name, value);
+        }
+    }
+
+    res.headers_mut()
+        .insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+
+    Ok(res)
+}
+
+/// Deletes the key when dropped, unless its response was stored.
+struct PendingKey {
+    db: PgPool,
+    key: Option<String>,
+}
+
+impl PendingKey {
+    /// Delete the key right away, so that a retry following the response can't find it.
+    async fn release(mut self) -> Result<()> {
+        if let Some(key) = self.key.take() {
+            delete_key(&self.db, &key).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for PendingKey {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+
+        let db = self.db.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = delete_key(&db, &key).await {
+                tracing::error!(error = ?e, "failed to release idempotency key");
+            }
+        });
+    }
+}
+
+async fn delete_key(db: &PgPool, key: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"delete from idempotency_key where idempotency_key = $1"#,
+        key
+    )
+    .execute(db)
+    .instrument(query_span("delete idempotency key"))
+    .await?;
+
+    Ok(())
+}
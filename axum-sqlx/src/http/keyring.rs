@@ -0,0 +1,251 @@
+use anyhow::{anyhow, bail, Context};
+use axum::extract::State;
+use axum::routing::get;
+use axum::{Json, Router};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::http::ApiContext;
+
+pub(crate) fn router() -> Router<ApiContext> {
+    Router::new().route("/.well-known/jwks.json", get(get_jwks))
+}
+
+/// The keys used to sign and verify the JWTs handed out by `AuthUser::to_jwt`.
+///
+/// New tokens are always signed with the current key and tagged with its `kid`. Tokens are
+/// verified against the key matching their `kid`, which may be the current key or one of the
+/// previous keys kept around so that rotating the signing key doesn't log everyone out.
+pub struct Keyring {
+    current_kid: String,
+    signing_key: EncodingKey,
+    keys: HashMap<String, VerifyingKey>,
+}
+
+struct VerifyingKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+    /// The public half of an asymmetric key, as published on `/.well-known/jwks.json`.
+    /// HMAC keys are secret and never have one.
+    jwk: Option<Jwk>,
+}
+
+#[derive(Serialize, Clone)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Serialize, Clone)]
+struct Jwk {
+    kid: String,
+    #[serde(rename = "use")]
+    use_: &'static str,
+    alg: &'static str,
+    #[serde(flatten)]
+    params: JwkParams,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "kty")]
+enum JwkParams {
+    #[serde(rename = "RSA")]
+    Rsa { n: String, e: String },
+    #[serde(rename = "OKP")]
+    Okp { crv: &'static str, x: String },
+}
+
+impl Keyring {
+    /// Build the keyring described by `config`.
+    ///
+    /// The current key is `JWT_PRIVATE_KEY` (RS256 or EdDSA, depending on the key type) if set,
+    /// otherwise `HMAC_KEY` (HS384). Previous keys are given as `kid=secret` pairs in
+    /// `JWT_PREVIOUS_KEYS` and `kid=path/to/public.pem` pairs in `JWT_PREVIOUS_PUBLIC_KEYS`.
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let current_kid = config.jwt_key_id.clone();
+        let mut keys = HashMap::new();
+
+        let signing_key = match (&config.jwt_private_key, &config.hmac_key) {
+            (Some(path), _) => {
+                let (signing_key, verifying_key) = load_private_key(&current_kid, path)?;
+                keys.insert(current_kid.clone(), verifying_key);
+                signing_key
+            }
+            (None, Some(secret)) => {
+                keys.insert(current_kid.clone(), hmac_key(secret));
+                EncodingKey::from_secret(secret.as_bytes())
+            }
+            (None, None) => bail!("either JWT_PRIVATE_KEY or HMAC_KEY must be set"),
+        };
+
+        for entry in &config.jwt_previous_keys {
+            let (kid, secret) = split_entry(entry)?;
+            insert_previous(&mut keys, kid, hmac_key(secret))?;
+        }
+
+        for entry in &config.jwt_previous_public_keys {
+            let (kid, path) = split_entry(entry)?;
+            insert_previous(&mut keys, kid, load_public_key(kid, Path::new(path))?)?;
+        }
+
+        Ok(Self {
+            current_kid,
+            signing_key,
+            keys,
+        })
+    }
+
+    /// Sign `claims` with the current key.
+    pub fn sign<T: Serialize>(&self, claims: &T) -> String {
+        let current = &self.keys[&self.current_kid];
+
+        let mut header = Header::new(current.algorithm);
+        header.kid = Some(self.current_kid.clone());
+
+        jsonwebtoken::encode(&header, claims, &self.signing_key)
+            .expect("JWT signing should be infallible")
+    }
+
+    /// Verify `token` and its `exp` claim, returning its claims.
+    ///
+    /// Tokens without a `kid` were issued before keys were tagged; those are tried against every
+    /// key using the algorithm named in their header.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> anyhow::Result<T> {
+        let header = jsonwebtoken::decode_header(token)?;
+
+        let candidates: Vec<&VerifyingKey> = match &header.kid {
+            Some(kid) => vec![self
+                .keys
+                .get(kid)
+                .ok_or_else(|| anyhow!("unknown key id {:?}", kid))?],
+            None => self
+                .keys
+                .values()
+                .filter(|key| key.algorithm == header.alg)
+                .collect(),
+        };
+
+        let mut last_error = anyhow!("no key for algorithm {:?}", header.alg);
+
+        for key in candidates {
+            let mut validation = Validation::new(key.algorithm);
+            validation.leeway = 0;
+
+            match jsonwebtoken::decode::<T>(token, &key.key, &validation) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) => last_error = e.into(),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn jwks(&self) -> JwkSet {
+        let mut keys: Vec<Jwk> = self
+            .keys
+            .values()
+            .filter_map(|key| key.jwk.clone())
+            .collect();
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+
+        JwkSet { keys }
+    }
+}
+
+/// Publish the public keys tokens may be verified with, so other services can check them
+/// without sharing a secret.
+async fn get_jwks(ctx: State<ApiContext>) -> Json<JwkSet> {
+    Json(ctx.keyring.jwks())
+}
+
+fn hmac_key(secret: &str) -> VerifyingKey {
+    VerifyingKey {
+        algorithm: Algorithm::HS384,
+        key: DecodingKey::from_secret(secret.as_bytes()),
+        jwk: None,
+    }
+}
+
+fn split_entry(entry: &str) -> anyhow::Result<(&str, &str)> {
+    entry
+        .split_once('=')
+        .filter(|(kid, value)| !kid.is_empty() && !value.is_empty())
+        .ok_or_else(|| anyhow!("expected `kid=value`, got {:?}", entry))
+}
+
+fn insert_previous(
+    keys: &mut HashMap<String, VerifyingKey>,
+    kid: &str,
+    key: VerifyingKey,
+) -> anyhow::Result<()> {
+    if keys.insert(kid.to_string(), key).is_some() {
+        bail!("duplicate key id {:?}", kid);
+    }
+
+    Ok(())
+}
+
+fn load_private_key(kid: &str, path: &Path) -> anyhow::Result<(EncodingKey, VerifyingKey)> {
+    let pem = std::fs::read(path).with_context(|| format!("failed to read {:?}", path))?;
+    let pkey = PKey::private_key_from_pem(&pem)
+        .with_context(|| format!("failed to parse private key {:?}", path))?;
+
+    let signing_key = match pkey.id() {
+        Id::RSA => EncodingKey::from_rsa_der(&pkey.rsa()?.private_key_to_der()?),
+        Id::ED25519 => EncodingKey::from_ed_der(&pkey.private_key_to_pkcs8()?),
+        id => bail!("unsupported key type {:?} in {:?}", id, path),
+    };
+
+    Ok((signing_key, verifying_key(kid, &pkey)?))
+}
+
+fn load_public_key(kid: &str, path: &Path) -> anyhow::Result<VerifyingKey> {
+    let pem = std::fs::read(path).with_context(|| format!("failed to read {:?}", path))?;
+    let pkey = PKey::public_key_from_pem(&pem)
+        .with_context(|| format!("failed to parse public key {:?}", path))?;
+
+    verifying_key(kid, &pkey)
+}
+
+fn verifying_key<T: HasPublic>(kid: &str, pkey: &PKeyRef<T>) -> anyhow::Result<VerifyingKey> {
+    let (algorithm, alg, params) = match pkey.id() {
+        Id::RSA => {
+            let rsa = pkey.rsa()?;
+            let params = JwkParams::Rsa {
+                n: URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                e: URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+            };
+            (Algorithm::RS256, "RS256", params)
+        }
+        Id::ED25519 => {
+            let params = JwkParams::Okp {
+                crv: "Ed25519",
+                x: URL_SAFE_NO_PAD.encode(pkey.raw_public_key()?),
+            };
+            (Algorithm::EdDSA, "EdDSA", params)
+        }
+        id => bail!("unsupported key type {:?}", id),
+    };
+
+    let key = match &params {
+        JwkParams::Rsa { n, e } => DecodingKey::from_rsa_components(n, e)?,
+        JwkParams::Okp { x, .. } => DecodingKey::from_ed_components(x)?,
+    };
+
+    Ok(VerifyingKey {
+        algorithm,
+        key,
+        jwk: Some(Jwk {
+            kid: kid.to_string(),
+            use_: "sig",
+            alg,
+            params,
+        }),
+    })
+}
@@ -1,48 +1,195 @@
 use anyhow::Context;
-use axum::Router;
+use axum::extract::MatchedPath;
+use axum::http::{Request, Response};
+use axum::{middleware, Router};
 use sqlx::PgPool;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal;
+use tower_http::compression::CompressionLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
+use tracing::field::Empty;
+use tracing::Span;
 
 use crate::config::Config;
+use crate::mailer::{self, Mailer};
 
+mod caching;
 mod error;
 mod extractor;
+mod idempotency;
+mod keyring;
 mod types;
 
 mod articles;
+mod health;
 mod profiles;
 mod users;
 
 pub use error::Error;
+pub use keyring::Keyring;
+pub use users::hash_password;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(Clone)]
-pub(crate) struct ApiContext {
+pub struct ApiContext {
     config: Arc<Config>,
     db: PgPool,
+    keyring: Arc<Keyring>,
+    mailer: Arc<dyn Mailer>,
+    article_events: articles::ArticleEvents,
+}
+
+impl ApiContext {
+    /// This spawns the task feeding `/api/articles/feed/live`, so it has to be called from within
+    /// a Tokio runtime.
+    pub fn new(config: Config, db: PgPool) -> anyhow::Result<Self> {
+        let keyring = Keyring::from_config(&config).context("Failed to load JWT keys")?;
+        let mailer = mailer::from_config(&config).context("Failed to create mailer")?;
+
+        Ok(Self {
+            config: Arc::new(config),
+            article_events: articles::ArticleEvents::listen(db.clone()),
+            db,
+            keyring: Arc::new(keyring),
+            mailer,
+        })
+    }
 }
 
 pub async fn serve(config: Config, db: PgPool) -> anyhow::Result<()> {
-    let api_context = ApiContext {
-        config: Arc::new(config),
-        db,
-    };
+    let addr = config.bind_address;
+    let app = api_router(ApiContext::new(config, db.clone())?);
 
-    let app = api_router(api_context);
+    tracing::info!("listening on http://{}", addr);
 
-    axum::Server::bind(&"0.0.0.0:8080".parse()?)
+    // Stop accepting new connections on a shutdown signal, wait for the in-flight
+    // requests to complete and only then close the pool they may still be using.
+    axum::Server::bind(&addr)
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
         .await
-        .context("Failed to run server")
+        .context("Failed to run server")?;
+
+    db.close().await;
+
+    Ok(())
 }
 
-fn api_router(api_context: ApiContext) -> Router {
+/// The endpoints creating resources that clients may safely retry with an `Idempotency-Key`.
+///
+/// Not `/api/users`: its responses carry the new user's token, which would be stored in plain text
+/// with the key. Retried signups fail on the taken username and email instead.
+const IDEMPOTENT_POSTS: &[&str] = &["/api/articles"];
+
+pub fn api_router(api_context: ApiContext) -> Router {
+    let idempotency = idempotency::IdempotencyLayer::new(api_context.db.clone(), IDEMPOTENT_POSTS);
+
     Router::new()
         .merge(users::router())
         .merge(profiles::router())
         .merge(articles::router())
-        .layer(TraceLayer::new_for_http())
+        .merge(health::router())
+        .merge(keyring::router())
+        .layer(idempotency)
+        .layer(middleware::from_fn(error::problem_details))
+        .layer(CompressionLayer::new())
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_response(record_response),
+        )
+        // Reuse the caller's `x-request-id` if it sent one, and echo it back either way.
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
         .with_state(api_context)
 }
+
+/// The root span of a request, named after its route template so that spans of e.g.
+/// `GET /api/articles/:slug` group together regardless of the slug.
+///
+/// `user_id` is filled in by the `AuthUser` extractor when the request is authenticated.
+fn request_span<B>(req: &Request<B>) -> Span {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("<unmatched>", |path| path.as_str());
+
+    tracing::info_span!(
+        "http.request",
+        otel.name = %format_args!("{} {}", req.method(), route),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.method = %req.method(),
+        http.route = route,
+        http.target = %req.uri(),
+        http.status_code = Empty,
+        request_id = req
+            .headers()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok()),
+        user_id = Empty,
+    )
+}
+
+fn record_response<B>(res: &Response<B>, latency: Duration, span: &Span) {
+    let status = res.status();
+
+    span.record("http.status_code", i64::from(status.as_u16()));
+
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+
+    tracing::info!(
+        status = status.as_u16(),
+        latency_ms = latency.as_millis() as u64,
+        "finished processing request"
+    );
+}
+
+/// A client span for a single database query, so query timings show up as children of the
+/// request span:
+///
+/// ```rust,ignore
+/// sqlx::query!("select 1 as one")
+///     .fetch_one(&ctx.db)
+///     .instrument(query_span("select one"))
+///     .await?;
+/// ```
+fn query_span(name: &'static str) -> Span {
+    tracing::info_span!(
+        "db.query",
+        otel.name = name,
+        otel.kind = "client",
+        db.system = "postgresql",
+    )
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::info!("signal received, starting graceful shutdown");
+}
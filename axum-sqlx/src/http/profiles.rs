@@ -1,10 +1,16 @@
 use axum::extract::{Path, State};
+use axum::middleware;
 use axum::routing::{get, post};
 use axum::{Json, Router};
+use sqlx::PgExecutor;
+use tracing::Instrument;
 
-use crate::http::error::ResultExt;
+use uuid::Uuid;
+
+use crate::http::caching;
+use crate::http::error::{ConstraintKind, ResultExt};
 use crate::http::extractor::{AuthUser, MaybeAuthUser};
-use crate::http::ApiContext;
+use crate::http::{query_span, ApiContext};
 use crate::http::{Error, Result};
 
 pub(crate) fn router() -> Router<ApiContext> {
@@ -14,6 +20,15 @@ pub(crate) fn router() -> Router<ApiContext> {
             "/api/profiles/:username/follow",
             post(follow_user).delete(unfollow_user),
         )
+        .route(
+            "/api/profiles/:username/block",
+            post(block_user).delete(unblock_user),
+        )
+        .route(
+            "/api/profiles/:username/mute",
+            post(mute_user).delete(unmute_user),
+        )
+        .route_layer(middleware::from_fn(caching::conditional_get))
 }
 
 #[derive(serde::Serialize)]
@@ -49,17 +64,102 @@ async fn get_user_profile(
                 ) "following!" -- This tells SQLx that this column will never be null
             from "user"
             where username = $1
+            -- users the viewer blocked or muted are hidden from them
+            and not exists(
+                select 1 from user_block
+                where blocked_user_id = "user".user_id and blocking_user_id = $2
+            )
+            and not exists(
+                select 1 from user_mute
+                where muted_user_id = "user".user_id and muting_user_id = $2
+            )
         "#,
         username,
         maybe_auth_user.user_id()
     )
     .fetch_optional(&ctx.db)
+    .instrument(query_span("select profile"))
     .await?
     .ok_or(Error::NotFound)?;
 
     Ok(Json(ProfileBody { profile }))
 }
 
+struct UserRow {
+    user_id: Uuid,
+    username: String,
+    bio: String,
+    image: Option<String>,
+}
+
+impl UserRow {
+    fn into_profile(self, following: bool) -> Profile {
+        Profile {
+            username: self.username,
+            bio: self.bio,
+            image: self.image,
+            following,
+        }
+    }
+}
+
+async fn fetch_user(db: impl PgExecutor<'_>, username: &str) -> Result<UserRow> {
+    sqlx::query_as!(
+        UserRow,
+        r#"select user_id, username, bio, image from "user" where username = $1"#,
+        username,
+    )
+    .fetch_optional(db)
+    .instrument(query_span("select user by username"))
+    .await?
+    .ok_or(Error::NotFound)
+}
+
+/// Whether `blocking_user_id` has blocked `blocked_user_id`.
+pub(in crate::http) async fn is_blocked(
+    db: impl PgExecutor<'_>,
+    blocking_user_id: Uuid,
+    blocked_user_id: Uuid,
+) -> Result<bool> {
+    let blocked = sqlx::query_scalar!(
+        r#"
+            select exists(
+                select 1 from user_block
+                where blocking_user_id = $1 and blocked_user_id = $2
+            ) "blocked!"
+        "#,
+        blocking_user_id,
+        blocked_user_id,
+    )
+    .fetch_one(db)
+    .instrument(query_span("select block"))
+    .await?;
+
+    Ok(blocked)
+}
+
+async fn is_following(
+    db: impl PgExecutor<'_>,
+    following_user_id: Uuid,
+    followed_user_id: Uuid,
+) -> Result<bool> {
+    let following = sqlx::query_scalar!(
+        r#"
+            select exists(
+                select 1 from follow
+                where following_user_id = $1 and followed_user_id = $2
+            ) "following!"
+        "#,
+        following_user_id,
+        followed_user_id,
+    )
+    .fetch_one(db)
+    .instrument(query_span("select follow"))
+    .await?;
+
+    Ok(following)
+}
+
 async fn follow_user(
     auth_user: AuthUser,
     ctx: State<ApiContext>,
@@ -67,13 +167,11 @@ async fn follow_user(
 ) -> Result<Json<ProfileBody>> {
     let mut tx = ctx.db.begin().await?;
 
-    let user = sqlx::query!(
-        r#"select user_id, username, bio, image from "user" where username = $1"#,
-        username,
-    )
-    .fetch_optional(&mut tx)
-    .await?
-    .ok_or(Error::NotFound)?;
+    let user = fetch_user(&mut tx, &username).await?;
+
+    if is_blocked(&mut tx, user.user_id, auth_user.user_id).await? {
+        return Err(Error::Forbidden);
+    }
 
     sqlx::query!(
         r#"insert into follow(following_user_id, followed_user_id) values ($1, $2) on conflict do nothing"#,
@@ -81,18 +179,15 @@ async fn follow_user(
         user.user_id,
     )
     .execute(&mut tx)
+    .instrument(query_span("insert follow"))
     .await
-    .on_constraint("user_cannot_follow_self", |_| Error::Forbidden)?;
+    .on_constraint("user_cannot_follow_self", |_| Error::Forbidden)
+    .on_constraint_kind(ConstraintKind::ForeignKey, |_| Error::Unauthorized)?;
 
     tx.commit().await?;
 
     Ok(Json(ProfileBody {
-        profile: Profile {
-            username: user.username,
-            bio: user.bio,
-            image: user.image,
-            following: true,
-        },
+        profile: user.into_profile(true),
     }))
 }
 
@@ -103,13 +198,7 @@ async fn unfollow_user(
 ) -> Result<Json<ProfileBody>> {
     let mut tx = ctx.db.begin().await?;
 
-    let user = sqlx::query!(
-        r#"select user_id, username, bio, image from "user" where username = $1"#,
-        username,
-    )
-    .fetch_optional(&mut tx)
-    .await?
-    .ok_or(Error::NotFound)?;
+    let user = fetch_user(&mut tx, &username).await?;
 
     sqlx::query!(
         r#"delete from follow where following_user_id = $1 and followed_user_id = $2"#,
@@ -117,16 +206,134 @@ async fn unfollow_user(
         user.user_id,
     )
     .execute(&mut tx)
+    .instrument(query_span("delete follow"))
     .await?;
 
     tx.commit().await?;
 
     Ok(Json(ProfileBody {
-        profile: Profile {
-            username: user.username,
-            bio: user.bio,
-            image: user.image,
-            following: false,
-        },
+        profile: user.into_profile(false),
+    }))
+}
+
+/// Blocking also ends follows in both directions, since the blocked user may no longer follow.
+async fn block_user(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(username): Path<String>,
+) -> Result<Json<ProfileBody>> {
+    let mut tx = ctx.db.begin().await?;
+
+    let user = fetch_user(&mut tx, &username).await?;
+
+    sqlx::query!(
+        r#"insert into user_block(blocking_user_id, blocked_user_id) values ($1, $2) on conflict do nothing"#,
+        auth_user.user_id,
+        user.user_id,
+    )
+    .execute(&mut tx)
+    .instrument(query_span("insert block"))
+    .await
+    .on_constraint("user_cannot_block_self", |_| Error::Forbidden)
+    .on_constraint_kind(ConstraintKind::ForeignKey, |_| Error::Unauthorized)?;
+
+    sqlx::query!(
+        r#"
+            delete from follow
+            where (following_user_id = $1 and followed_user_id = $2)
+            or (following_user_id = $2 and followed_user_id = $1)
+        "#,
+        auth_user.user_id,
+        user.user_id,
+    )
+    .execute(&mut tx)
+    .instrument(query_span("delete follows"))
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ProfileBody {
+        profile: user.into_profile(false),
+    }))
+}
+
+async fn unblock_user(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(username): Path<String>,
+) -> Result<Json<ProfileBody>> {
+    let mut tx = ctx.db.begin().await?;
+
+    let user = fetch_user(&mut tx, &username).await?;
+
+    sqlx::query!(
+        r#"delete from user_block where blocking_user_id = $1 and blocked_user_id = $2"#,
+        auth_user.user_id,
+        user.user_id,
+    )
+    .execute(&mut tx)
+    .instrument(query_span("delete block"))
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ProfileBody {
+        profile: user.into_profile(false),
+    }))
+}
+
+async fn mute_user(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(username): Path<String>,
+) -> Result<Json<ProfileBody>> {
+    let mut tx = ctx.db.begin().await?;
+
+    let user = fetch_user(&mut tx, &username).await?;
+
+    sqlx::query!(
+        r#"insert into user_mute(muting_user_id, muted_user_id) values ($1, $2) on conflict do nothing"#,
+        auth_user.user_id,
+        user.user_id,
+    )
+    .execute(&mut tx)
+    .instrument(query_span("insert mute"))
+    .await
+    .on_constraint("user_cannot_mute_self", |_| Error::Forbidden)
+    .on_constraint_kind(ConstraintKind::ForeignKey, |_| Error::Unauthorized)?;
+
+    let following = is_following(&mut tx, auth_user.user_id, user.user_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ProfileBody {
+        profile: user.into_profile(following),
+    }))
+}
+
+async fn unmute_user(
+    auth_user: AuthUser,
+    ctx: State<ApiContext>,
+    Path(username): Path<String>,
+) -> Result<Json<ProfileBody>> {
+    let mut tx = ctx.db.begin().await?;
+
+    let user = fetch_user(&mut tx, &username).await?;
+
+    sqlx::query!(
+        r#"delete from user_mute where muting_user_id = $1 and muted_user_id = $2"#,
+        auth_user.user_id,
+        user.user_id,
+    )
+    .execute(&mut tx)
+    .instrument(query_span("delete mute"))
+    .await?;
+
+    let following = is_following(&mut tx, auth_user.user_id, user.user_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ProfileBody {
+        profile: user.into_profile(following),
     }))
 }
@@ -3,17 +3,29 @@ use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, Pa
 use axum::extract::State;
 use axum::routing::{get, post};
 use axum::{Json, Router};
+use tracing::Instrument;
 
 use crate::http::error::Error;
 use crate::http::extractor::AuthUser;
-use crate::http::{ApiContext, Result};
+use crate::http::{query_span, ApiContext, Result};
 
 use super::error::ResultExt;
 
+mod tokens;
+
 pub(crate) fn router() -> Router<ApiContext> {
     Router::new()
         .route("/api/users", post(create_user))
         .route("/api/users/login", post(login_user))
+        .route("/api/users/verify", post(tokens::verify_email))
+        .route(
+            "/api/users/password-reset",
+            post(tokens::request_password_reset),
+        )
+        .route(
+            "/api/users/password-reset/confirm",
+            post(tokens::confirm_password_reset),
+        )
         .route("/api/user", get(get_current_user).put(update_user))
 }
 
@@ -71,6 +83,7 @@ async fn create_user(
         password_hash,
     )
     .fetch_one(&ctx.db)
+    .instrument(query_span("insert user"))
     .await
     .on_constraint("user_username_key", |_| {
         Error::unprocessable_entity([("username", "username taken")])
@@ -79,6 +92,11 @@ async fn create_user(
         Error::unprocessable_entity([("email", "email taken")])
     })?;
 
+    // The account is usable right away; failing to send the email shouldn't undo the signup.
+    if let Err(e) = tokens::send_verification_email(&ctx, user_id, req.user.email.clone()).await {
+        tracing::error!("failed to send verification email: {:?}", e);
+    }
+
     Ok(Json(UserBody {
         user: User {
             username: req.user.username,
@@ -96,18 +114,24 @@ async fn login_user(
 ) -> Result<Json<UserBody<User>>> {
     let user = sqlx::query!(
         r#"
-            select user_id, email, username, bio, image, password_hash
+            select user_id, email, username, bio, image, password_hash, disabled_at
             from "user"
             where email = $1
         "#,
         req.user.email,
     )
     .fetch_optional(&ctx.db)
+    .instrument(query_span("select user by email"))
     .await?
     .ok_or(Error::unprocessable_entity([("email", "does not exist")]))?;
 
     verify_password(req.user.password, user.password_hash).await?;
 
+    // Only tell whoever knows the password that the account has been disabled.
+    if user.disabled_at.is_some() {
+        return Err(Error::Forbidden);
+    }
+
     Ok(Json(UserBody {
         user: User {
             username: user.username,
@@ -135,6 +159,7 @@ async fn get_current_user(
         auth_user.user_id,
     )
     .fetch_one(&ctx.db)
+    .instrument(query_span("select user"))
     .await?;
 
     Ok(Json(UserBody {
@@ -163,6 +188,23 @@ async fn update_user(
         None
     };
 
+    let mut tx = ctx.db.begin().await?;
+
+    let old_email = sqlx::query_scalar!(
+        r#"select email from "user" where user_id = $1 for update"#,
+        auth_user.user_id,
+    )
+    .fetch_one(&mut tx)
+    .instrument(query_span("select user email"))
+    .await?;
+
+    // A new address has to be verified again.
+    let email_changed = req
+        .user
+        .email
+        .as_ref()
+        .is_some_and(|email| *email != old_email);
+
     let user = sqlx::query!(
         r#"
             update "user"
@@ -170,8 +212,9 @@ async fn update_user(
                 email = coalesce($2, "user".email),
                 password_hash = coalesce($3, "user".password_hash),
                 bio = coalesce($4, "user".bio),
-                image = coalesce($5, "user".image)
-            where user_id = $6
+                image = coalesce($5, "user".image),
+                email_verified_at = case when $6 then null else "user".email_verified_at end
+            where user_id = $7
             returning email, username, bio, image
         "#,
         req.user.username,
@@ -179,9 +222,11 @@ async fn update_user(
         password_hash,
         req.user.bio,
         req.user.image,
+        email_changed,
         auth_user.user_id,
     )
-    .fetch_one(&ctx.db)
+    .fetch_one(&mut tx)
+    .instrument(query_span("update user"))
     .await
     .on_constraint("user_username_key", |_| {
         Error::unprocessable_entity([("username", "username taken")])
@@ -190,6 +235,21 @@ async fn update_user(
         Error::unprocessable_entity([("email", "email taken")])
     })?;
 
+    if email_changed {
+        tokens::retire_verification_tokens(&mut tx, auth_user.user_id).await?;
+    }
+
+    tx.commit().await?;
+
+    // Like at signup, the update stands even if the email can't be sent.
+    if email_changed {
+        if let Err(e) =
+            tokens::send_verification_email(&ctx, auth_user.user_id, user.email.clone()).await
+        {
+            tracing::error!("failed to send verification email: {:?}", e);
+        }
+    }
+
     Ok(Json(UserBody {
         user: User {
             username: user.username,
@@ -201,8 +261,11 @@ async fn update_user(
     }))
 }
 
-async fn hash_password(password: String) -> Result<String> {
-    Ok(tokio::task::spawn_blocking(move || -> Result<String> {
+/// Hash a password with Argon2 on the blocking thread pool.
+///
+/// This is public so `axum-sqlx-admin` stores passwords exactly the way signup does.
+pub async fn hash_password(password: String) -> Result<String> {
+    tokio::task::spawn_blocking(move || -> Result<String> {
         let salt = SaltString::generate(rand::thread_rng());
         Ok(Argon2::default()
             .hash_password(password.as_bytes(), &salt)
@@ -210,11 +273,11 @@ async fn hash_password(password: String) -> Result<String> {
             .to_string())
     })
     .await
-    .context("panic in geratating password hash")??)
+    .context("panic in geratating password hash")?
 }
 
 async fn verify_password(password: String, password_hash: String) -> Result<()> {
-    Ok(tokio::task::spawn_blocking(move || -> Result<()> {
+    tokio::task::spawn_blocking(move || -> Result<()> {
         let parsed_hash = PasswordHash::new(&password_hash)
             .map_err(|e| anyhow::anyhow!("failed to parse password hash: {}", e))?;
         Argon2::default()
@@ -224,5 +287,5 @@ async fn verify_password(password: String, password_hash: String) -> Result<()>
         Ok(())
     })
     .await
-    .context("panic in verifying password")??)
+    .context("panic in verifying password")?
 }
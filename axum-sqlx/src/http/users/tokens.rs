@@ -0,0 +1,265 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use sqlx::PgExecutor;
+use time::{Duration, OffsetDateTime};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::http::error::Error;
+use crate::http::extractor::AuthUser;
+use crate::http::users::{hash_password, User, UserBody};
+use crate::http::{query_span, ApiContext, Result};
+use crate::mailer::Email;
+
+#[derive(serde::Deserialize)]
+pub(super) struct VerifyEmail {
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct RequestPasswordReset {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct ConfirmPasswordReset {
+    token: String,
+    password: String,
+}
+
+/// What a token mailed to a user allows them to do.
+#[derive(Clone, Copy)]
+enum Purpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl Purpose {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::VerifyEmail => "verify_email",
+            Self::ResetPassword => "reset_password",
+        }
+    }
+
+    /// How long a token stays usable after it was issued.
+    fn lifetime(self) -> Duration {
+        match self {
+            Self::VerifyEmail => Duration::days(1),
+            Self::ResetPassword => Duration::hours(1),
+        }
+    }
+}
+
+pub(super) async fn verify_email(
+    ctx: State<ApiContext>,
+    Json(req): Json<UserBody<VerifyEmail>>,
+) -> Result<Json<UserBody<User>>> {
+    let mut tx = ctx.db.begin().await?;
+
+    let user_id = consume_token(&mut tx, &req.user.token, Purpose::VerifyEmail).await?;
+
+    let user = sqlx::query!(
+        r#"
+            update "user"
+            set email_verified_at = coalesce("user".email_verified_at, now())
+            where user_id = $1
+            returning email, username, bio, image
+        "#,
+        user_id,
+    )
+    .fetch_one(&mut tx)
+    .instrument(query_span("update user verified"))
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(UserBody {
+        user: User {
+            username: user.username,
+            email: user.email,
+            token: AuthUser { user_id }.to_jwt(&ctx),
+            bio: user.bio,
+            image: user.image,
+        },
+    }))
+}
+
+/// Mail a password reset token to the user with the given email, if there is one.
+///
+/// This always answers `202 Accepted` so it can't be used to find out who has an account.
+pub(super) async fn request_password_reset(
+    ctx: State<ApiContext>,
+    Json(req): Json<UserBody<RequestPasswordReset>>,
+) -> Result<StatusCode> {
+    let user = sqlx::query!(
+        r#"select user_id, email from "user" where email = $1 and disabled_at is null"#,
+        req.user.email,
+    )
+    .fetch_optional(&ctx.db)
+    .instrument(query_span("select user by email"))
+    .await?;
+
+    if let Some(user) = user {
+        let token = issue_token(&ctx.db, user.user_id, Purpose::ResetPassword).await?;
+
+        ctx.mailer
+            .send(Email {
+                to: user.email,
+                subject: "Reset your password".to_string(),
+                body: format!(
+                    "Someone asked to reset the password of your account.\n\n\
+                     To choose a new password, send this token to POST /api/users/password-reset/confirm \
+                     within the next hour:\n\n{}\n\n\
+                     If this wasn't you, you can ignore this email.",
+                    token
+                ),
+            })
+            .await?;
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub(super) async fn confirm_password_reset(
+    ctx: State<ApiContext>,
+    Json(req): Json<UserBody<ConfirmPasswordReset>>,
+) -> Result<Json<UserBody<User>>> {
+    let password_hash = hash_password(req.user.password).await?;
+
+    let mut tx = ctx.db.begin().await?;
+
+    let user_id = consume_token(&mut tx, &req.user.token, Purpose::ResetPassword).await?;
+
+    // Receiving the token also proves the user owns the email address.
+    let user = sqlx::query!(
+        r#"
+            update "user"
+            set password_hash = $1,
+                email_verified_at = coalesce("user".email_verified_at, now())
+            where user_id = $2
+            returning email, username, bio, image
+        "#,
+        password_hash,
+        user_id,
+    )
+    .fetch_one(&mut tx)
+    .instrument(query_span("update user password"))
+    .await?;
+
+    // Any other reset link that is still in someone's inbox is now stale.
+    retire_tokens(&mut tx, user_id, Purpose::ResetPassword).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(UserBody {
+        user: User {
+            username: user.username,
+            email: user.email,
+            token: AuthUser { user_id }.to_jwt(&ctx),
+            bio: user.bio,
+            image: user.image,
+        },
+    }))
+}
+
+/// Mail a user a token to verify their email address with, after signing up or changing it.
+pub(super) async fn send_verification_email(
+    ctx: &ApiContext,
+    user_id: Uuid,
+    email: String,
+) -> Result<()> {
+    let token = issue_token(&ctx.db, user_id, Purpose::VerifyEmail).await?;
+
+    ctx.mailer
+        .send(Email {
+            to: email,
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "To verify your email address, send this token to POST /api/users/verify \
+                 within the next 24 hours:\n\n{}",
+                token
+            ),
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Store a new token for `user_id` and return it. Only its hash is persisted.
+async fn issue_token(db: impl PgExecutor<'_>, user_id: Uuid, purpose: Purpose) -> Result<String> {
+    let token = URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>());
+
+    sqlx::query!(
+        r#"
+            insert into user_token (token_hash, user_id, purpose, expires_at)
+            values ($1, $2, $3, $4)
+        "#,
+        hash_token(&token),
+        user_id,
+        purpose.as_str(),
+        OffsetDateTime::now_utc() + purpose.lifetime(),
+    )
+    .execute(db)
+    .instrument(query_span("insert user_token"))
+    .await?;
+
+    Ok(token)
+}
+
+/// Mark the unused verification tokens of `user_id` as used, so the ones mailed to an address
+/// they no longer have can't verify the new one.
+pub(super) async fn retire_verification_tokens(
+    db: impl PgExecutor<'_>,
+    user_id: Uuid,
+) -> Result<()> {
+    retire_tokens(db, user_id, Purpose::VerifyEmail).await
+}
+
+async fn retire_tokens(db: impl PgExecutor<'_>, user_id: Uuid, purpose: Purpose) -> Result<()> {
+    sqlx::query!(
+        r#"
+            update user_token
+            set used_at = now()
+            where user_id = $1 and purpose = $2 and used_at is null
+        "#,
+        user_id,
+        purpose.as_str(),
+    )
+    .execute(db)
+    .instrument(query_span("retire user_tokens"))
+    .await?;
+
+    Ok(())
+}
+
+/// Mark `token` as used and return the user it was issued to, if it is valid for `purpose`,
+/// unused and unexpired.
+async fn consume_token(db: impl PgExecutor<'_>, token: &str, purpose: Purpose) -> Result<Uuid> {
+    sqlx::query_scalar!(
+        r#"
+            update user_token
+            set used_at = now()
+            where token_hash = $1
+              and purpose = $2
+              and used_at is null
+              and expires_at > now()
+            returning user_id
+        "#,
+        hash_token(token),
+        purpose.as_str(),
+    )
+    .fetch_optional(db)
+    .instrument(query_span("consume user_token"))
+    .await?
+    .ok_or_else(|| Error::unprocessable_entity([("token", "invalid or expired")]))
+}
+
+fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
@@ -1,2 +1,4 @@
 pub mod config;
 pub mod http;
+pub mod mailer;
+pub mod telemetry;
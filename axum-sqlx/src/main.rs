@@ -1,32 +1,47 @@
 use anyhow::Context;
-use sqlx::postgres::PgPoolOptions;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use std::str::FromStr;
+use std::time::Duration;
 
 use axum_sqlx::config::Config;
-use axum_sqlx::http;
+use axum_sqlx::{http, telemetry};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // read .env file
     dotenvy::dotenv().ok();
 
-    // init the logger
-    env_logger::init();
-
     // parse our configuration from the environment;
     let config = envy::from_env::<Config>().context("Failed to parse environment")?;
 
+    // init logging and span export
+    telemetry::init(&config)?;
+
+    let connect_options = PgConnectOptions::from_str(&config.database_url)
+        .context("Failed to parse DATABASE_URL")?
+        .options([(
+            "statement_timeout",
+            config.database_statement_timeout_ms.to_string(),
+        )]);
+
     // create a single connection pool
     let db = PgPoolOptions::new()
-        .max_connections(50)
-        .connect(&config.database_url)
+        .max_connections(config.database_max_connections)
+        .min_connections(config.database_min_connections)
+        .acquire_timeout(Duration::from_secs(config.database_acquire_timeout_secs))
+        .connect_with(connect_options)
         .await
         .context("Failed to create connection pool")?;
 
     // run the migrations
-    sqlx::migrate!().run(&db).await?;
+    if config.migrate_on_start {
+        sqlx::migrate!().run(&db).await?;
+    }
+
+    // run http server until a shutdown signal is received
+    let res = http::serve(config, db).await;
 
-    // run http server
-    http::serve(config, db).await?;
+    telemetry::shutdown().await;
 
-    Ok(())
+    res
 }
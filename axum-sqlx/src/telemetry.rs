@@ -0,0 +1,173 @@
+use anyhow::Context;
+use futures::future::BoxFuture;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue, Value};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::trace::{self as sdktrace, Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use serde::Deserialize;
+use serde_json::{json, Map};
+use std::fmt;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::Config;
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceExporter {
+    /// Only log to stdout; spans are not exported.
+    #[default]
+    None,
+    /// Export spans over OTLP/gRPC, to `OTEL_EXPORTER_OTLP_ENDPOINT` (`http://localhost:4317` by default).
+    Otlp,
+    /// Print finished spans to stdout, one JSON object per line.
+    Stdout,
+    /// Append finished spans to `TRACE_FILE`, one JSON object per line.
+    File,
+}
+
+/// Install the global `tracing` subscriber: human-readable logs filtered by `RUST_LOG`, plus an
+/// OpenTelemetry layer exporting spans as selected by `TRACE_EXPORTER`.
+///
+/// Records emitted through the `log` crate, such as sqlx's per-query logs, are forwarded as
+/// events of the span they happened in.
+pub fn init(config: &Config) -> anyhow::Result<()> {
+    let trace_config = sdktrace::config().with_resource(Resource::new([KeyValue::new(
+        "service.name",
+        config.service_name.clone(),
+    )]));
+
+    let tracer = match config.trace_exporter {
+        TraceExporter::None => None,
+        TraceExporter::Otlp => Some(
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+                .with_trace_config(trace_config)
+                .install_batch(runtime::Tokio)
+                .context("Failed to install OTLP exporter")?,
+        ),
+        TraceExporter::Stdout => Some(install(
+            JsonExporter(Box::new(std::io::stdout())),
+            trace_config,
+        )),
+        TraceExporter::File => {
+            let path = config
+                .trace_file
+                .as_ref()
+                .context("TRACE_FILE must be set when TRACE_EXPORTER=file")?;
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open {:?}", path))?;
+
+            Some(install(JsonExporter(Box::new(file)), trace_config))
+        }
+    };
+
+    tracing_subscriber::registry()
+        .with(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new("axum_sqlx=info,tower_http=info")),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+        .try_init()
+        .context("Failed to install tracing subscriber")?;
+
+    Ok(())
+}
+
+/// Flush spans that haven't been exported yet.
+pub async fn shutdown() {
+    // This blocks until the batch processor has drained its queue.
+    if let Err(e) = tokio::task::spawn_blocking(global::shutdown_tracer_provider).await {
+        eprintln!("failed to shut down tracer provider: {:?}", e);
+    }
+}
+
+fn install(exporter: JsonExporter, trace_config: sdktrace::Config) -> Tracer {
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_config(trace_config)
+        .build();
+
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    global::set_tracer_provider(provider);
+
+    tracer
+}
+
+/// Writes finished spans as JSON lines, for local use without a collector.
+struct JsonExporter(Box<dyn Write + Send + Sync>);
+
+impl fmt::Debug for JsonExporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("JsonExporter")
+    }
+}
+
+impl SpanExporter for JsonExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let result = batch.iter().try_for_each(|span| {
+            serde_json::to_writer(&mut self.0, &span_to_json(span))?;
+            self.0.write_all(b"\n")
+        });
+
+        let result = result
+            .and_then(|_| self.0.flush())
+            .map_err(|e| opentelemetry::trace::TraceError::Other(Box::new(e)));
+
+        Box::pin(std::future::ready(result))
+    }
+}
+
+fn span_to_json(span: &SpanData) -> serde_json::Value {
+    let attributes = |attributes: &mut dyn Iterator<Item = &KeyValue>| {
+        attributes
+            .map(|kv| (kv.key.to_string(), value_to_json(&kv.value)))
+            .collect::<Map<_, _>>()
+    };
+
+    json!({
+        "traceId": span.span_context.trace_id().to_string(),
+        "spanId": span.span_context.span_id().to_string(),
+        "parentSpanId": span.parent_span_id.to_string(),
+        "name": span.name,
+        "kind": format!("{:?}", span.span_kind),
+        "startTimeUnixNano": unix_nanos(span.start_time),
+        "endTimeUnixNano": unix_nanos(span.end_time),
+        "durationMs": span
+            .end_time
+            .duration_since(span.start_time)
+            .unwrap_or_default()
+            .as_secs_f64() * 1000.0,
+        "attributes": attributes(&mut span.attributes.iter()),
+        "events": span.events.iter().map(|event| json!({
+            "name": event.name,
+            "timeUnixNano": unix_nanos(event.timestamp),
+            "attributes": attributes(&mut event.attributes.iter()),
+        })).collect::<Vec<_>>(),
+        "status": format!("{:?}", span.status),
+    })
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Bool(b) => json!(b),
+        Value::I64(i) => json!(i),
+        Value::F64(f) => json!(f),
+        other => json!(other.as_str()),
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
@@ -0,0 +1,203 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::process::{Command, Output};
+
+use common::TestApp;
+
+/// The URL of the database of the test.
+async fn database_url(db: &PgPool) -> String {
+    let database: String = sqlx::query_scalar("select current_database()")
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+    dotenvy::dotenv().ok();
+    let base_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let (server, _) = base_url.rsplit_once('/').unwrap();
+
+    format!("{}/{}", server, database)
+}
+
+/// Run `axum-sqlx-admin` against the database of the test.
+async fn admin(db: &PgPool, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_axum-sqlx-admin"))
+        .args(args)
+        .env("DATABASE_URL", database_url(db).await)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    output
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> StatusCode {
+    app.post(
+        "/api/users/login",
+        None,
+        json!({ "user": { "email": email, "password": password } }),
+    )
+    .await
+    .status
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn migrate_status(db: PgPool) {
+    let output = admin(&db, &["migrate", "status"]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stdout.lines().count() >= 7);
+    assert!(
+        stdout.lines().all(|line| line.ends_with("applied")),
+        "{}",
+        stdout
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn migrate_down_and_up(db: PgPool) {
+    admin(&db, &["migrate", "down"]).await;
+    admin(&db, &["migrate", "down", "--target", "4"]).await;
+
+    let output = admin(&db, &["migrate", "status"]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for line in stdout.lines() {
+        let version: i64 = line.split_whitespace().next().unwrap().parse().unwrap();
+        let expected = if version <= 4 { "applied" } else { "pending" };
+        assert!(line.ends_with(expected), "{}", stdout);
+    }
+
+    // the original schema has no down scripts
+    let output = Command::new(env!("CARGO_BIN_EXE_axum-sqlx-admin"))
+        .args(["migrate", "down"])
+        .env("DATABASE_URL", database_url(&db).await)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    admin(&db, &["migrate", "up"]).await;
+    let output = admin(&db, &["migrate", "status"]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.lines().all(|line| line.ends_with("applied")),
+        "{}",
+        stdout
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn user_lifecycle(db: PgPool) {
+    let app = TestApp::new(db.clone());
+
+    admin(
+        &db,
+        &[
+            "user",
+            "create",
+            "--username",
+            "admin",
+            "--email",
+            "admin@example.com",
+            "--password",
+            "password123",
+        ],
+    )
+    .await;
+
+    assert_eq!(
+        login(&app, "admin@example.com", "password123").await,
+        StatusCode::OK
+    );
+    let token = app
+        .post(
+            "/api/users/login",
+            None,
+            json!({ "user": { "email": "admin@example.com", "password": "password123" } }),
+        )
+        .await
+        .body["user"]["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        app.get("/api/user", Some(&token)).await.status,
+        StatusCode::OK
+    );
+
+    admin(&db, &["user", "disable", "admin"]).await;
+
+    assert_eq!(
+        login(&app, "admin@example.com", "password123").await,
+        StatusCode::FORBIDDEN
+    );
+    // tokens issued before don't work either
+    assert_eq!(
+        app.get("/api/user", Some(&token)).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        app.put(
+            "/api/user",
+            Some(&token),
+            json!({ "user": { "bio": "still here" } })
+        )
+        .await
+        .status,
+        StatusCode::UNAUTHORIZED
+    );
+
+    admin(&db, &["user", "enable", "admin"]).await;
+    assert_eq!(
+        app.get("/api/user", Some(&token)).await.status,
+        StatusCode::OK
+    );
+    admin(
+        &db,
+        &["user", "reset-password", "admin", "--password", "hunter22"],
+    )
+    .await;
+
+    assert_eq!(
+        login(&app, "admin@example.com", "hunter22").await,
+        StatusCode::OK
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn seed_and_export(db: PgPool) {
+    admin(
+        &db,
+        &[
+            "seed",
+            "--users",
+            "5",
+            "--articles-per-user",
+            "2",
+            "--follows-per-user",
+            "10",
+            "--favorites-per-user",
+            "3",
+        ],
+    )
+    .await;
+
+    let output = admin(&db, &["export"]).await;
+    let export: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let len = |key: &str| export[key].as_array().unwrap().len();
+    assert_eq!(len("users"), 5);
+    // capped by the number of other users
+    assert_eq!(len("follows"), 5 * 4);
+    assert_eq!(len("articles"), 5 * 2);
+    assert_eq!(len("favorites"), 5 * 3);
+    assert_eq!(len("comments"), 0);
+
+    assert!(export["users"][0].get("passwordHash").is_none());
+}
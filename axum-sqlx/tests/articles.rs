@@ -0,0 +1,317 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+
+use common::TestApp;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn create_article(db: PgPool) {
+    let app = TestApp::new(db);
+    let token = app.create_user("john").await;
+
+    let res = app
+        .post(
+            "/api/articles",
+            Some(&token),
+            json!({
+                "article": {
+                    "title": "How to Train Your Dragon",
+                    "description": "Ever wonder how?",
+                    "body": "You have to believe",
+                    "tagList": ["training", "dragons"],
+                }
+            }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+
+    let article = &res.body["article"];
+    assert_eq!(article["slug"], "how-to-train-your-dragon");
+    assert_eq!(article["title"], "How to Train Your Dragon");
+    assert_eq!(article["description"], "Ever wonder how?");
+    assert_eq!(article["body"], "You have to believe");
+    assert_eq!(article["tagList"], json!(["dragons", "training"]));
+    assert_eq!(article["favorited"], false);
+    assert_eq!(article["favoritesCount"], 0);
+    assert_eq!(article["author"]["username"], "john");
+    assert_eq!(article["author"]["following"], false);
+    assert!(article["createdAt"].is_string());
+    assert!(article["updatedAt"].is_string());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn create_article_errors(db: PgPool) {
+    let app = TestApp::new(db);
+    let token = app.create_user("john").await;
+
+    let body = json!({
+        "article": {"title": "Hello", "description": "d", "body": "b", "tagList": []}
+    });
+
+    let res = app.post("/api/articles", None, body.clone()).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    app.create_article(&token, "Hello", &[]).await;
+
+    let res = app.post("/api/articles", Some(&token), body).await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        res.body,
+        json!({"errors": {"slug": ["duplicate article slug: hello"]}})
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn list_articles(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+
+    app.create_article(&john, "First", &["rust"]).await;
+    app.create_article(&jane, "Second", &["go"]).await;
+    app.create_article(&john, "Third", &["rust", "axum"]).await;
+
+    let res = app.get("/api/articles", None).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["articlesCount"], 3);
+    let slugs: Vec<_> = res.body["articles"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|article| article["slug"].as_str().unwrap())
+        .collect();
+    assert_eq!(slugs, ["third", "second", "first"]);
+
+    let res = app.get("/api/articles?tag=rust", None).await;
+
+    assert_eq!(res.body["articlesCount"], 2);
+
+    let res = app.get("/api/articles?author=jane", None).await;
+
+    assert_eq!(res.body["articlesCount"], 1);
+    assert_eq!(res.body["articles"][0]["slug"], "second");
+
+    let res = app.get("/api/articles?limit=1&offset=1", None).await;
+
+    assert_eq!(res.body["articlesCount"], 1);
+    assert_eq!(res.body["articles"][0]["slug"], "second");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn feed_articles(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+    let bob = app.create_user("bob").await;
+
+    app.create_article(&jane, "From Jane", &[]).await;
+    app.create_article(&bob, "From Bob", &[]).await;
+
+    let res = app.get("/api/articles/feed", None).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = app.get("/api/articles/feed", Some(&john)).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body, json!({"articles": [], "articlesCount": 0}));
+
+    app.post("/api/profiles/jane/follow", Some(&john), json!({}))
+        .await;
+
+    let res = app.get("/api/articles/feed", Some(&john)).await;
+
+    assert_eq!(res.body["articlesCount"], 1);
+    assert_eq!(res.body["articles"][0]["slug"], "from-jane");
+    assert_eq!(res.body["articles"][0]["author"]["following"], true);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn get_article(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+
+    let slug = app
+        .create_article(&jane, "Hello World", &["greeting"])
+        .await;
+
+    let res = app.get(&format!("/api/articles/{}", slug), None).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["article"]["slug"], "hello-world");
+    assert_eq!(res.body["article"]["author"]["username"], "jane");
+    assert_eq!(res.body["article"]["author"]["following"], false);
+
+    app.post("/api/profiles/jane/follow", Some(&john), json!({}))
+        .await;
+
+    let res = app
+        .get(&format!("/api/articles/{}", slug), Some(&john))
+        .await;
+
+    assert_eq!(res.body["article"]["author"]["following"], true);
+
+    let res = app.get("/api/articles/does-not-exist", None).await;
+
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn render_markdown(db: PgPool) {
+    let app = TestApp::new(db.clone());
+    let john = app.create_user("john").await;
+
+    let res = app
+        .post(
+            "/api/articles",
+            Some(&john),
+            json!({
+                "article": {
+                    "title": "Dragons",
+                    "description": "description",
+                    "body": "# Dragons\n\nThey *fly*.<script>alert(1)</script>",
+                    "tagList": [],
+                }
+            }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        res.body["article"]["bodyHtml"],
+        "<h1 id=\"user-content-dragons\">Dragons</h1>\n<p>They <em>fly</em>.</p>\n"
+    );
+    assert_eq!(
+        res.body["article"]["toc"],
+        json!([{ "level": 1, "text": "Dragons", "id": "user-content-dragons" }])
+    );
+    assert_eq!(res.body["article"]["readingTimeMinutes"], 1);
+
+    // the cached HTML is refreshed along with the body
+    let res = app
+        .put(
+            "/api/articles/dragons",
+            Some(&john),
+            json!({ "article": { "body": "## Unicorns" } }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        res.body["article"]["bodyHtml"],
+        "<h2 id=\"user-content-unicorns\">Unicorns</h2>\n"
+    );
+
+    let cached: Option<String> =
+        sqlx::query_scalar("select body_html from article where slug = 'dragons'")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+
+    assert_eq!(cached.as_deref(), res.body["article"]["bodyHtml"].as_str());
+
+    // a new body rendering to the same HTML still counts as cached
+    let res = app
+        .put(
+            "/api/articles/dragons",
+            Some(&john),
+            json!({ "article": { "body": "## Unicorns  \n" } }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        res.body["article"]["bodyHtml"],
+        "<h2 id=\"user-content-unicorns\">Unicorns</h2>\n"
+    );
+
+    let up_to_date: bool = sqlx::query_scalar(
+        "select body_html is not null and body_html_source_hash = sha256(convert_to(body, 'UTF8')) \
+         from article where slug = 'dragons'",
+    )
+    .fetch_one(&db)
+    .await
+    .unwrap();
+
+    assert!(up_to_date);
+
+    // bodies changed behind the API's back are rendered when read
+    sqlx::query("update article set body = '*Fire*' where slug = 'dragons'")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let res = app.get("/api/articles/dragons", None).await;
+
+    assert_eq!(res.body["article"]["bodyHtml"], "<p><em>Fire</em></p>\n");
+
+    let res = app
+        .post(
+            "/api/articles/dragons/comments",
+            Some(&john),
+            json!({ "comment": { "body": "**Nice**" } }),
+        )
+        .await;
+
+    assert_eq!(
+        res.body["comment"]["bodyHtml"],
+        "<p><strong>Nice</strong></p>\n"
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn update_article(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+    let slug = app.create_article(&john, "Dragons", &["fantasy"]).await;
+    app.create_article(&john, "Unicorns", &[]).await;
+
+    let uri = format!("/api/articles/{}", slug);
+
+    let res = app
+        .put(
+            &uri,
+            Some(&jane),
+            json!({ "article": { "description": "mine now" } }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app
+        .put(
+            &uri,
+            Some(&john),
+            json!({ "article": { "title": "Unicorns" } }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let res = app
+        .put(
+            &uri,
+            Some(&john),
+            json!({ "article": { "title": "Red Dragons", "tagList": ["red", "fantasy"] } }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["article"]["slug"], "red-dragons");
+    assert_eq!(res.body["article"]["description"], "description");
+    assert_eq!(res.body["article"]["tagList"], json!(["fantasy", "red"]));
+
+    assert_eq!(app.get(&uri, None).await.status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        app.get("/api/articles/red-dragons", None).await.status,
+        StatusCode::OK
+    );
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+
+use common::TestApp;
+
+fn usernames(body: &serde_json::Value, key: &str) -> Vec<String> {
+    body[key]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["author"]["username"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn block_hides_author_and_forbids_interaction(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+    let slug = app.create_article(&jane, "Jane's article", &[]).await;
+    app.create_article(&john, "John's article", &[]).await;
+
+    let res = app
+        .post("/api/profiles/jane/follow", Some(&john), json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app
+        .post("/api/profiles/jane/block", Some(&john), json!({}))
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["profile"]["following"], false);
+
+    // jane's content is hidden from john
+    let res = app.get("/api/articles", Some(&john)).await;
+    assert_eq!(usernames(&res.body, "articles"), ["john"]);
+
+    let res = app.get("/api/articles/feed", Some(&john)).await;
+    assert_eq!(res.body["articles"], json!([]));
+
+    let res = app.get("/api/profiles/jane", Some(&john)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    // but not from anyone else
+    let res = app.get("/api/articles", None).await;
+    assert_eq!(usernames(&res.body, "articles").len(), 2);
+
+    // jane may neither follow john nor comment on his articles
+    let res = app
+        .post("/api/profiles/john/follow", Some(&jane), json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let john_slug = "johns-article";
+    let res = app
+        .post(
+            &format!("/api/articles/{}/comments", john_slug),
+            Some(&jane),
+            json!({ "comment": { "body": "hi" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    // john can still comment on jane's article, but won't see jane's replies
+    let uri = format!("/api/articles/{}/comments", slug);
+    app.post(&uri, Some(&jane), json!({ "comment": { "body": "mine" } }))
+        .await;
+    let res = app
+        .post(&uri, Some(&john), json!({ "comment": { "body": "his" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get(&uri, Some(&john)).await;
+    assert_eq!(usernames(&res.body, "comments"), ["john"]);
+
+    let res = app.get(&uri, None).await;
+    assert_eq!(usernames(&res.body, "comments"), ["jane", "john"]);
+
+    // unblocking restores everything
+    let res = app.delete("/api/profiles/jane/block", Some(&john)).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/api/profiles/jane", Some(&john)).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app
+        .post("/api/profiles/john/follow", Some(&jane), json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn mute_hides_author_only(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+    app.create_article(&jane, "Jane's article", &[]).await;
+
+    app.post("/api/profiles/jane/follow", Some(&john), json!({}))
+        .await;
+
+    let res = app
+        .post("/api/profiles/jane/mute", Some(&john), json!({}))
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["profile"]["following"], true);
+
+    let res = app.get("/api/articles", Some(&john)).await;
+    assert_eq!(res.body["articles"], json!([]));
+
+    let res = app.get("/api/articles/feed", Some(&john)).await;
+    assert_eq!(res.body["articles"], json!([]));
+
+    let res = app.get("/api/profiles/jane", Some(&john)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    // the muted user can still interact
+    let res = app
+        .post("/api/profiles/john/follow", Some(&jane), json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.delete("/api/profiles/jane/mute", Some(&john)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["profile"]["following"], true);
+
+    let res = app.get("/api/articles/feed", Some(&john)).await;
+    assert_eq!(usernames(&res.body, "articles"), ["jane"]);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn block_and_mute_errors(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+
+    for action in ["block", "mute"] {
+        let uri = format!("/api/profiles/john/{}", action);
+
+        let res = app.post(&uri, None, json!({})).await;
+        assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+        let res = app.post(&uri, Some(&john), json!({})).await;
+        assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+        let res = app
+            .post(
+                &format!("/api/profiles/nobody/{}", action),
+                Some(&john),
+                json!({}),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::NOT_FOUND);
+    }
+}
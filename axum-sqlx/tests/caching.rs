@@ -0,0 +1,123 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::header::{
+    ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, ETAG, IF_NONE_MATCH, VARY,
+};
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use sqlx::PgPool;
+use std::io::Read;
+
+use common::TestApp;
+
+async fn get_if_none_match(app: &TestApp, uri: &str, etag: &str) -> StatusCode {
+    let req = Request::get(uri)
+        .header(IF_NONE_MATCH, etag)
+        .body(Body::empty())
+        .unwrap();
+
+    app.send(req).await.status
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn conditional_get(db: PgPool) {
+    let app = TestApp::new(db);
+    let token = app.create_user("john").await;
+    let slug = app.create_article(&token, "Hello", &[]).await;
+
+    for uri in [
+        "/api/articles".to_string(),
+        format!("/api/articles/{}", slug),
+        "/api/profiles/john".to_string(),
+    ] {
+        let res = app.get(&uri, None).await;
+
+        assert_eq!(res.status, StatusCode::OK);
+        let etag = res.headers[ETAG].to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/\""), "{}", etag);
+
+        assert_eq!(
+            get_if_none_match(&app, &uri, &etag).await,
+            StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(
+            get_if_none_match(&app, &uri, &format!("\"other\", {}", etag)).await,
+            StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(
+            get_if_none_match(&app, &uri, "W/\"other\"").await,
+            StatusCode::OK
+        );
+    }
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn etag_changes_with_content(db: PgPool) {
+    let app = TestApp::new(db);
+    let token = app.create_user("john").await;
+
+    let before = app.get("/api/profiles/john", None).await.headers[ETAG].clone();
+
+    app.put("/api/user", Some(&token), json!({"user": {"bio": "hello"}}))
+        .await;
+
+    let after = app.get("/api/profiles/john", None).await.headers[ETAG].clone();
+
+    assert_ne!(before, after);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn cache_control_depends_on_authentication(db: PgPool) {
+    let app = TestApp::new(db);
+    let token = app.create_user("john").await;
+
+    let res = app.get("/api/articles", None).await;
+
+    assert_eq!(res.headers[CACHE_CONTROL], "public, no-cache");
+    assert!(res
+        .headers
+        .get_all(VARY)
+        .iter()
+        .any(|value| value == "Authorization"));
+
+    let res = app.get("/api/articles", Some(&token)).await;
+
+    assert_eq!(res.headers[CACHE_CONTROL], "private, no-cache");
+
+    // writes are not cached
+    let res = app
+        .post(
+            "/api/articles",
+            Some(&token),
+            json!({"article": {"title": "t", "description": "d", "body": "b", "tagList": []}}),
+        )
+        .await;
+
+    assert!(res.headers.get(ETAG).is_none());
+    assert!(res.headers.get(CACHE_CONTROL).is_none());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn gzip_compression(db: PgPool) {
+    let app = TestApp::new(db);
+    let token = app.create_user("john").await;
+    app.create_article(&token, "Hello", &[]).await;
+
+    let req = Request::get("/api/articles")
+        .header(ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.send(req).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers[CONTENT_ENCODING], "gzip");
+
+    let mut json = String::new();
+    flate2::read::GzDecoder::new(&res.bytes[..])
+        .read_to_string(&mut json)
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(body["articlesCount"], 1);
+}
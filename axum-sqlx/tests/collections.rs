@@ -0,0 +1,155 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use common::TestApp;
+
+fn slugs(body: &Value) -> Vec<&str> {
+    body["articles"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|article| article["slug"].as_str().unwrap())
+        .collect()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn add_reorder_and_remove_articles(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+    let dragons = app.create_article(&jane, "Dragons", &[]).await;
+    let unicorns = app.create_article(&jane, "Unicorns", &[]).await;
+
+    let res = app
+        .post(
+            "/api/collections",
+            Some(&john),
+            json!({ "collection": { "name": "Read later" } }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["collection"]["name"], "Read later");
+    assert_eq!(res.body["collection"]["articlesCount"], 0);
+
+    let uri = format!(
+        "/api/collections/{}/articles",
+        res.body["collection"]["id"].as_str().unwrap()
+    );
+
+    app.post(&format!("{}/{}", uri, dragons), Some(&john), json!({}))
+        .await;
+    let res = app
+        .post(&format!("{}/{}", uri, unicorns), Some(&john), json!({}))
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(slugs(&res.body), ["dragons", "unicorns"]);
+    assert_eq!(res.body["articles"][0]["author"]["username"], "jane");
+
+    // adding an article again keeps its position
+    let res = app
+        .post(&format!("{}/{}", uri, dragons), Some(&john), json!({}))
+        .await;
+
+    assert_eq!(slugs(&res.body), ["dragons", "unicorns"]);
+
+    let res = app
+        .put(
+            &uri,
+            Some(&john),
+            json!({ "articles": ["unicorns", "dragons"] }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(slugs(&res.body), ["unicorns", "dragons"]);
+
+    let res = app
+        .put(&uri, Some(&john), json!({ "articles": ["unicorns"] }))
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let res = app
+        .delete(&format!("{}/{}", uri, unicorns), Some(&john))
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(slugs(&res.body), ["dragons"]);
+
+    let res = app.get("/api/collections", Some(&john)).await;
+
+    assert_eq!(res.body["collections"][0]["articlesCount"], 1);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn collections_are_private(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+    let dragons = app.create_article(&jane, "Dragons", &[]).await;
+
+    let res = app
+        .post(
+            "/api/collections",
+            Some(&john),
+            json!({ "collection": { "name": "Read later" } }),
+        )
+        .await;
+    let collection = format!(
+        "/api/collections/{}",
+        res.body["collection"]["id"].as_str().unwrap()
+    );
+
+    let res = app
+        .post(
+            "/api/collections",
+            Some(&john),
+            json!({ "collection": { "name": "read LATER" } }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let res = app
+        .get(&format!("{}/articles", collection), Some(&jane))
+        .await;
+
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app
+        .post(
+            &format!("{}/articles/{}", collection, dragons),
+            Some(&jane),
+            json!({}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app.delete(&collection, Some(&jane)).await;
+
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app.get(&format!("{}/articles", collection), None).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = app.get("/api/collections", Some(&jane)).await;
+
+    assert_eq!(res.body["collections"], json!([]));
+
+    let res = app.delete(&collection, Some(&john)).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app
+        .get(&format!("{}/articles", collection), Some(&john))
+        .await;
+
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+
+use common::TestApp;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn add_list_and_delete_comments(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+    let slug = app.create_article(&john, "Dragons", &[]).await;
+
+    let uri = format!("/api/articles/{}/comments", slug);
+
+    let res = app
+        .post(&uri, Some(&jane), json!({ "comment": { "body": "Nice!" } }))
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["comment"]["body"], "Nice!");
+    assert_eq!(res.body["comment"]["author"]["username"], "jane");
+
+    let id = res.body["comment"]["id"].as_i64().unwrap();
+
+    let res = app.get(&uri, None).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["comments"].as_array().unwrap().len(), 1);
+    assert_eq!(res.body["comments"][0]["id"], id);
+
+    let res = app.delete(&format!("{}/{}", uri, id), Some(&john)).await;
+
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app.delete(&format!("{}/{}", uri, id), Some(&jane)).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(app.get(&uri, None).await.body["comments"], json!([]));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn comment_errors(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+    let slug = app.create_article(&john, "Dragons", &[]).await;
+
+    let body = json!({ "comment": { "body": "Nice!" } });
+
+    let res = app
+        .post(
+            &format!("/api/articles/{}/comments", slug),
+            None,
+            body.clone(),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = app
+        .post("/api/articles/nope/comments", Some(&john), body)
+        .await;
+
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app
+        .delete(&format!("/api/articles/{}/comments/1", slug), Some(&john))
+        .await;
+
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
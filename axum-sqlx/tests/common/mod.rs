@@ -0,0 +1,162 @@
+#![allow(dead_code)]
+
+use axum::body::{Body, Bytes};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::net::{SocketAddr, TcpListener};
+use tower::ServiceExt;
+
+use axum_sqlx::config::Config;
+use axum_sqlx::http::{api_router, ApiContext};
+
+/// The router under test, backed by the per-test database handed out by `#[sqlx::test]`.
+pub struct TestApp {
+    router: Router,
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// The parsed JSON body, or a `Value::String` if the body was not JSON.
+    pub body: Value,
+    pub bytes: Bytes,
+}
+
+impl TestApp {
+    pub fn new(db: PgPool) -> Self {
+        Self::with_env(db, [("HMAC_KEY", "test-hmac-key")])
+    }
+
+    /// Build the app from the given environment variables on top of `DATABASE_URL`.
+    pub fn with_env<'a>(db: PgPool, env: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        // Go through `envy` so that fields with `serde(default)` are filled the same way as in `main`.
+        let config: Config = envy::from_iter(
+            [("DATABASE_URL", "")]
+                .into_iter()
+                .chain(env)
+                .map(|(k, v)| (k.to_string(), v.to_string())),
+        )
+        .expect("test config should be valid");
+
+        Self {
+            router: api_router(ApiContext::new(config, db).expect("test keys should be valid")),
+        }
+    }
+
+    /// Serve the router on an ephemeral port, for tests that need a real connection such as
+    /// WebSockets.
+    pub fn spawn_server(&self) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(self.router.clone().into_make_service());
+        tokio::spawn(server);
+
+        addr
+    }
+
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> TestResponse {
+        let mut req = Request::builder().method(method).uri(uri);
+
+        if let Some(token) = token {
+            req = req.header(AUTHORIZATION, format!("Token {}", token));
+        }
+
+        let req = match body {
+            Some(body) => req
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => req.body(Body::empty()),
+        }
+        .unwrap();
+
+        self.send(req).await
+    }
+
+    pub async fn send(&self, req: Request<Body>) -> TestResponse {
+        let res = self.router.clone().oneshot(req).await.unwrap();
+
+        let status = res.status();
+        let headers = res.headers().clone();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+
+        TestResponse {
+            status,
+            headers,
+            body,
+            bytes,
+        }
+    }
+
+    pub async fn get(&self, uri: &str, token: Option<&str>) -> TestResponse {
+        self.request(Method::GET, uri, token, None).await
+    }
+
+    pub async fn post(&self, uri: &str, token: Option<&str>, body: Value) -> TestResponse {
+        self.request(Method::POST, uri, token, Some(body)).await
+    }
+
+    pub async fn put(&self, uri: &str, token: Option<&str>, body: Value) -> TestResponse {
+        self.request(Method::PUT, uri, token, Some(body)).await
+    }
+
+    pub async fn delete(&self, uri: &str, token: Option<&str>) -> TestResponse {
+        self.request(Method::DELETE, uri, token, None).await
+    }
+
+    /// Register a user named `username` and return their token.
+    pub async fn create_user(&self, username: &str) -> String {
+        let res = self
+            .post(
+                "/api/users",
+                None,
+                json!({
+                    "user": {
+                        "username": username,
+                        "email": format!("{}@example.com", username),
+                        "password": "password123",
+                    }
+                }),
+            )
+            .await;
+
+        assert_eq!(res.status, StatusCode::OK, "{}", res.body);
+
+        res.body["user"]["token"].as_str().unwrap().to_string()
+    }
+
+    /// Publish an article as the owner of `token` and return its slug.
+    pub async fn create_article(&self, token: &str, title: &str, tags: &[&str]) -> String {
+        let res = self
+            .post(
+                "/api/articles",
+                Some(token),
+                json!({
+                    "article": {
+                        "title": title,
+                        "description": "description",
+                        "body": "body",
+                        "tagList": tags,
+                    }
+                }),
+            )
+            .await;
+
+        assert_eq!(res.status, StatusCode::OK, "{}", res.body);
+
+        res.body["article"]["slug"].as_str().unwrap().to_string()
+    }
+}
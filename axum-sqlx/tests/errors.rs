@@ -0,0 +1,141 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use sqlx::PgPool;
+
+use common::TestApp;
+
+fn get(uri: &str, accept: Option<&str>) -> Request<Body> {
+    let mut req = Request::get(uri);
+
+    if let Some(accept) = accept {
+        req = req.header(ACCEPT, accept);
+    }
+
+    req.body(Body::empty()).unwrap()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn realworld_format_by_default(db: PgPool) {
+    let app = TestApp::new(db);
+
+    let res = app.send(get("/api/user", None)).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.body, "authentication required");
+
+    let res = app.send(get("/api/user", Some("application/json"))).await;
+
+    assert_eq!(res.body, "authentication required");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn problem_json_when_accepted(db: PgPool) {
+    let app = TestApp::new(db);
+
+    let res = app
+        .send(get(
+            "/api/user",
+            Some("application/json, application/problem+json;q=0.9"),
+        ))
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.headers[CONTENT_TYPE], "application/problem+json");
+    assert_eq!(res.headers[WWW_AUTHENTICATE], "Token");
+    assert_eq!(res.body["type"], "about:blank");
+    assert_eq!(res.body["title"], "Unauthorized");
+    assert_eq!(res.body["status"], 401);
+    assert_eq!(res.body["code"], "unauthorized");
+    assert_eq!(res.body["detail"], "authentication required");
+    assert_eq!(
+        res.body["requestId"],
+        res.headers["x-request-id"].to_str().unwrap()
+    );
+
+    let res = app
+        .send(get(
+            "/api/articles/nope",
+            Some("application/problem+json;q=0"),
+        ))
+        .await;
+
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_ne!(res.headers[CONTENT_TYPE], "application/problem+json");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn problem_json_field_errors(db: PgPool) {
+    let app = TestApp::new(db);
+    app.create_user("john").await;
+
+    let req = Request::post("/api/users")
+        .header(ACCEPT, "application/problem+json")
+        .header(CONTENT_TYPE, "application/json")
+        .header("x-request-id", "test-request")
+        .body(Body::from(
+            json!({
+                "user": {
+                    "username": "john",
+                    "email": "john2@example.com",
+                    "password": "password123",
+                }
+            })
+            .to_string(),
+        ))
+        .unwrap();
+
+    let res = app.send(req).await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.headers["x-request-id"], "test-request");
+    assert_eq!(res.body["code"], "validation_failed");
+    assert_eq!(res.body["requestId"], "test-request");
+    assert_eq!(
+        res.body["errors"],
+        json!({ "username": ["username taken"] })
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn foreign_key_violation_of_deleted_user(db: PgPool) {
+    let app = TestApp::new(db.clone());
+    let token = app.create_user("john").await;
+    app.create_user("jane").await;
+
+    sqlx::query(r#"delete from "user" where username = 'john'"#)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let res = app
+        .post(
+            "/api/articles",
+            Some(&token),
+            json!({
+                "article": {
+                    "title": "Orphan",
+                    "description": "no author",
+                    "body": "body",
+                    "tagList": [],
+                }
+            }),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let req = Request::post("/api/profiles/jane/follow")
+        .header(AUTHORIZATION, format!("Token {}", token))
+        .header(ACCEPT, "application/problem+json")
+        .body(Body::empty())
+        .unwrap();
+
+    let res = app.send(req).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.body["code"], "unauthorized");
+}
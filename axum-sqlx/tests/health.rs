@@ -0,0 +1,22 @@
+mod common;
+
+use axum::http::StatusCode;
+use sqlx::PgPool;
+
+use common::TestApp;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn liveness_and_readiness(db: PgPool) {
+    let app = TestApp::new(db.clone());
+
+    assert_eq!(app.get("/health/live", None).await.status, StatusCode::OK);
+    assert_eq!(app.get("/health/ready", None).await.status, StatusCode::OK);
+
+    db.close().await;
+
+    assert_eq!(app.get("/health/live", None).await.status, StatusCode::OK);
+    assert_eq!(
+        app.get("/health/ready", None).await.status,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+}
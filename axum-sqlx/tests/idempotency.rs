@@ -0,0 +1,214 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{Method, Request, StatusCode};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use common::{TestApp, TestResponse};
+
+async fn post_with_key(
+    app: &TestApp,
+    uri: &str,
+    token: Option<&str>,
+    key: &str,
+    body: Value,
+) -> TestResponse {
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(CONTENT_TYPE, "application/json")
+        .header("Idempotency-Key", key);
+
+    if let Some(token) = token {
+        req = req.header(AUTHORIZATION, format!("Token {}", token));
+    }
+
+    app.send(req.body(Body::from(body.to_string())).unwrap())
+        .await
+}
+
+fn article(title: &str) -> Value {
+    json!({
+        "article": {
+            "title": title,
+            "description": "description",
+            "body": "body",
+            "tagList": [],
+        }
+    })
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn replays_create_article(db: PgPool) {
+    let app = TestApp::new(db.clone());
+    let john = app.create_user("john").await;
+
+    let first = post_with_key(
+        &app,
+        "/api/articles",
+        Some(&john),
+        "key-1",
+        article("Dragons"),
+    )
+    .await;
+
+    assert_eq!(first.status, StatusCode::OK);
+    assert!(first.headers.get("idempotent-replayed").is_none());
+
+    let retry = post_with_key(
+        &app,
+        "/api/articles",
+        Some(&john),
+        "key-1",
+        article("Dragons"),
+    )
+    .await;
+
+    assert_eq!(retry.status, StatusCode::OK);
+    assert_eq!(retry.headers["idempotent-replayed"], "true");
+    assert_eq!(retry.bytes, first.bytes);
+
+    let count: i64 = sqlx::query_scalar("select count(*) from article")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+    assert_eq!(count, 1);
+
+    let res = post_with_key(
+        &app,
+        "/api/articles",
+        Some(&john),
+        "key-1",
+        article("Unicorns"),
+    )
+    .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(res.body["errors"]["Idempotency-Key"].is_array());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn does_not_store_user_tokens(db: PgPool) {
+    let app = TestApp::new(db.clone());
+    let body = json!({
+        "user": {
+            "username": "john",
+            "email": "john@example.com",
+            "password": "password123",
+        }
+    });
+
+    let first = post_with_key(&app, "/api/users", None, "signup-john", body.clone()).await;
+    let retry = post_with_key(&app, "/api/users", None, "signup-john", body.clone()).await;
+
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(retry.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let stored: i64 = sqlx::query_scalar("select count(*) from idempotency_key")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+    assert_eq!(stored, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn failed_requests_release_the_key(db: PgPool) {
+    let app = TestApp::new(db);
+    let john = app.create_user("john").await;
+
+    let res = post_with_key(&app, "/api/articles", None, "key-1", article("Dragons")).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = post_with_key(
+        &app,
+        "/api/articles",
+        Some(&john),
+        "key-1",
+        article("Dragons"),
+    )
+    .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn rejects_retries_in_progress(db: PgPool) {
+    let app = TestApp::new(db.clone());
+    let john = app.create_user("john").await;
+
+    let res = post_with_key(
+        &app,
+        "/api/articles",
+        Some(&john),
+        "key-1",
+        article("Dragons"),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    // as if the first request was still being processed
+    sqlx::query(
+        "update idempotency_key set response_status = null, response_headers = null, response_body = null",
+    )
+    .execute(&db)
+    .await
+    .unwrap();
+
+    let res = post_with_key(
+        &app,
+        "/api/articles",
+        Some(&john),
+        "key-1",
+        article("Dragons"),
+    )
+    .await;
+
+    assert_eq!(res.status, StatusCode::CONFLICT);
+
+    // keys expire after a day
+    sqlx::query("update idempotency_key set created_at = now() - interval '25 hours'")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let res = post_with_key(
+        &app,
+        "/api/articles",
+        Some(&john),
+        "key-1",
+        article("Unicorns"),
+    )
+    .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn rejects_large_bodies(db: PgPool) {
+    let app = TestApp::new(db.clone());
+    let john = app.create_user("john").await;
+
+    let mut body = article("Dragons");
+    body["article"]["body"] = "a".repeat(3 * 1024 * 1024).into();
+
+    let res = post_with_key(&app, "/api/articles", Some(&john), "key-1", body).await;
+
+    assert_eq!(res.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    // the key wasn't used up
+    let res = post_with_key(
+        &app,
+        "/api/articles",
+        Some(&john),
+        "key-1",
+        article("Dragons"),
+    )
+    .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use axum::http::StatusCode;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use serde_json::json;
+use sqlx::PgPool;
+use std::path::PathBuf;
+
+use common::TestApp;
+
+/// Write `pem` to a fresh file in the temp directory and return its path.
+fn write_pem(pem: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("axum-sqlx-{:x}.pem", rand::random::<u64>()));
+    std::fs::write(&path, pem).unwrap();
+    path
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn tokens_survive_hmac_key_rotation(db: PgPool) {
+    let old = TestApp::with_env(
+        db.clone(),
+        [("HMAC_KEY", "old-secret"), ("JWT_KEY_ID", "k1")],
+    );
+    let token = old.create_user("john").await;
+
+    let rotated = TestApp::with_env(
+        db.clone(),
+        [
+            ("HMAC_KEY", "new-secret"),
+            ("JWT_KEY_ID", "k2"),
+            ("JWT_PREVIOUS_KEYS", "k1=old-secret"),
+        ],
+    );
+
+    let res = rotated.get("/api/user", Some(&token)).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["user"]["username"], "john");
+
+    // once the old key is dropped, its tokens are rejected
+    let retired = TestApp::with_env(db, [("HMAC_KEY", "new-secret"), ("JWT_KEY_ID", "k2")]);
+
+    let res = retired.get("/api/user", Some(&token)).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn tokens_without_kid_are_accepted(db: PgPool) {
+    let app = TestApp::with_env(db.clone(), [("HMAC_KEY", "secret")]);
+    app.create_user("john").await;
+
+    let user_id = sqlx::query_scalar::<_, uuid::Uuid>(r#"select user_id from "user""#)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+    // what `AuthUser::to_jwt` produced before keys were tagged with a `kid`
+    let token = jsonwebtoken::encode(
+        &Header::new(Algorithm::HS384),
+        &json!({"user_id": user_id, "exp": i64::MAX / 1000}),
+        &EncodingKey::from_secret(b"secret"),
+    )
+    .unwrap();
+
+    let res = app.get("/api/user", Some(&token)).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn expired_tokens_are_rejected(db: PgPool) {
+    let app = TestApp::with_env(db, [("HMAC_KEY", "secret"), ("SESSION_LENGTH_SECS", "-1")]);
+    let token = app.create_user("john").await;
+
+    let res = app.get("/api/user", Some(&token)).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn ed25519_signing_and_jwks(db: PgPool) {
+    let key = PKey::generate_ed25519().unwrap();
+    let path = write_pem(&key.private_key_to_pem_pkcs8().unwrap());
+
+    let app = TestApp::with_env(
+        db,
+        [
+            ("JWT_PRIVATE_KEY", path.to_str().unwrap()),
+            ("JWT_KEY_ID", "ed-1"),
+            ("JWT_PREVIOUS_KEYS", "hs-1=secret"),
+        ],
+    );
+    let token = app.create_user("john").await;
+
+    let header = jsonwebtoken::decode_header(&token).unwrap();
+    assert_eq!(header.alg, Algorithm::EdDSA);
+    assert_eq!(header.kid.as_deref(), Some("ed-1"));
+
+    let res = app.get("/api/user", Some(&token)).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/.well-known/jwks.json", None).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    // the HMAC secret must never be published
+    assert_eq!(res.body["keys"].as_array().unwrap().len(), 1);
+    assert_eq!(res.body["keys"][0]["kid"], "ed-1");
+    assert_eq!(res.body["keys"][0]["kty"], "OKP");
+    assert_eq!(res.body["keys"][0]["crv"], "Ed25519");
+    assert_eq!(res.body["keys"][0]["alg"], "EdDSA");
+    assert_eq!(res.body["keys"][0]["use"], "sig");
+    assert!(res.body["keys"][0]["x"].is_string());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn rsa_signing_with_previous_public_key(db: PgPool) {
+    let old_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let old_private = write_pem(&old_key.private_key_to_pem_pkcs8().unwrap());
+    let old_public = write_pem(&old_key.public_key_to_pem().unwrap());
+
+    let new_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let new_private = write_pem(&new_key.private_key_to_pem_pkcs8().unwrap());
+
+    let old = TestApp::with_env(
+        db.clone(),
+        [
+            ("JWT_PRIVATE_KEY", old_private.to_str().unwrap()),
+            ("JWT_KEY_ID", "rsa-1"),
+        ],
+    );
+    let token = old.create_user("john").await;
+
+    assert_eq!(
+        jsonwebtoken::decode_header(&token).unwrap().alg,
+        Algorithm::RS256
+    );
+
+    let previous_public_keys = format!("rsa-1={}", old_public.to_str().unwrap());
+    let rotated = TestApp::with_env(
+        db,
+        [
+            ("JWT_PRIVATE_KEY", new_private.to_str().unwrap()),
+            ("JWT_KEY_ID", "rsa-2"),
+            ("JWT_PREVIOUS_PUBLIC_KEYS", previous_public_keys.as_str()),
+        ],
+    );
+
+    let res = rotated.get("/api/user", Some(&token)).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = rotated.get("/.well-known/jwks.json", None).await;
+
+    let kids: Vec<_> = res.body["keys"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|key| (key["kid"].as_str().unwrap(), key["kty"].as_str().unwrap()))
+        .collect();
+    assert_eq!(kids, [("rsa-1", "RSA"), ("rsa-2", "RSA")]);
+    assert_eq!(res.body["keys"][0]["e"], "AQAB");
+}
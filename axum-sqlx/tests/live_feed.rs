@@ -0,0 +1,123 @@
+mod common;
+
+use axum::http::header::AUTHORIZATION;
+use axum::http::StatusCode;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use common::TestApp;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(addr: SocketAddr, token: Option<&str>) -> Result<Socket, WsError> {
+    let mut req = format!("ws://{}/api/articles/feed/live", addr)
+        .into_client_request()
+        .unwrap();
+
+    if let Some(token) = token {
+        req.headers_mut()
+            .insert(AUTHORIZATION, format!("Token {}", token).parse().unwrap());
+    }
+
+    let (socket, _) = tokio_tungstenite::connect_async(req).await?;
+
+    Ok(socket)
+}
+
+/// The listener is started in the background; wait until it issued its `LISTEN`.
+async fn wait_for_listener(db: &PgPool) {
+    for _ in 0..50 {
+        let listening: bool = sqlx::query_scalar(
+            r#"
+                select exists(
+                    select 1 from pg_stat_activity
+                    where datname = current_database() and query ilike 'listen%'
+                )
+            "#,
+        )
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+        if listening {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("live feed listener never started");
+}
+
+async fn next_article(socket: &mut Socket) -> Value {
+    let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+        .await
+        .expect("no article pushed in time")
+        .unwrap()
+        .unwrap();
+
+    match message {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        other => panic!("unexpected message {:?}", other),
+    }
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn pushes_articles_of_followed_users(db: PgPool) {
+    let app = TestApp::new(db.clone());
+    let john = app.create_user("john").await;
+    let jane = app.create_user("jane").await;
+    let bob = app.create_user("bob").await;
+
+    app.post("/api/profiles/jane/follow", Some(&john), json!({}))
+        .await;
+
+    let addr = app.spawn_server();
+    wait_for_listener(&db).await;
+
+    let mut socket = connect(addr, Some(&john)).await.unwrap();
+
+    // neither bob's nor john's own articles show up
+    app.create_article(&bob, "Bob's article", &[]).await;
+    app.create_article(&john, "John's article", &[]).await;
+    app.create_article(&jane, "Jane's article", &["dragons"])
+        .await;
+
+    let article = next_article(&mut socket).await;
+    assert_eq!(article["article"]["slug"], "janes-article");
+    assert_eq!(article["article"]["tagList"], json!(["dragons"]));
+    assert_eq!(article["article"]["author"]["username"], "jane");
+    assert_eq!(article["article"]["author"]["following"], true);
+
+    // following is checked when an article comes in, not when connecting
+    app.post("/api/profiles/bob/follow", Some(&john), json!({}))
+        .await;
+    app.create_article(&bob, "Bob's second article", &[]).await;
+
+    let article = next_article(&mut socket).await;
+    assert_eq!(article["article"]["slug"], "bobs-second-article");
+    assert_eq!(article["article"]["author"]["username"], "bob");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn requires_authentication(db: PgPool) {
+    let app = TestApp::new(db);
+    let addr = app.spawn_server();
+
+    match connect(addr, None).await {
+        Err(WsError::Http(res)) => assert_eq!(res.status(), StatusCode::UNAUTHORIZED),
+        other => panic!("expected 401, got {:?}", other.map(|_| ())),
+    }
+
+    match connect(addr, Some("not-a-token")).await {
+        Err(WsError::Http(res)) => assert_eq!(res.status(), StatusCode::UNAUTHORIZED),
+        other => panic!("expected 401, got {:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+
+use common::TestApp;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn get_profile(db: PgPool) {
+    let app = TestApp::new(db);
+    app.create_user("john").await;
+
+    let res = app.get("/api/profiles/john", None).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        res.body,
+        json!({"profile": {"username": "john", "bio": "", "image": null, "following": false}})
+    );
+
+    let res = app.get("/api/profiles/nobody", None).await;
+
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn follow_and_unfollow(db: PgPool) {
+    let app = TestApp::new(db);
+    let token = app.create_user("john").await;
+    app.create_user("jane").await;
+
+    let res = app
+        .post("/api/profiles/jane/follow", Some(&token), json!({}))
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["profile"]["following"], true);
+
+    let res = app.get("/api/profiles/jane", Some(&token)).await;
+
+    assert_eq!(res.body["profile"]["following"], true);
+
+    let res = app.delete("/api/profiles/jane/follow", Some(&token)).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["profile"]["following"], false);
+
+    let res = app.get("/api/profiles/jane", Some(&token)).await;
+
+    assert_eq!(res.body["profile"]["following"], false);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn follow_errors(db: PgPool) {
+    let app = TestApp::new(db);
+    let token = app.create_user("john").await;
+
+    let res = app.post("/api/profiles/john/follow", None, json!({})).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = app
+        .post("/api/profiles/nobody/follow", Some(&token), json!({}))
+        .await;
+
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app
+        .post("/api/profiles/john/follow", Some(&token), json!({}))
+        .await;
+
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,279 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+use std::path::{Path, PathBuf};
+
+use common::TestApp;
+
+/// An app that drops its emails into a fresh directory, which is returned alongside it.
+fn app_with_mail_dir(db: PgPool) -> (TestApp, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("axum-sqlx-mail-{:x}", rand::random::<u64>()));
+
+    let app = TestApp::with_env(
+        db,
+        [
+            ("HMAC_KEY", "test-hmac-key"),
+            ("MAILER", "file"),
+            ("MAIL_DIR", dir.to_str().unwrap()),
+        ],
+    );
+
+    (app, dir)
+}
+
+/// The tokens mailed to `to` so far, oldest first.
+fn mailed_tokens(dir: &Path, to: &str) -> Vec<String> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .filter(|mail| mail.starts_with(&format!("To: {}\n", to)))
+        .map(|mail| {
+            // tokens are 32 random bytes in unpadded base64, on a line of their own
+            mail.lines()
+                .find(|line| line.len() == 43 && !line.contains(' '))
+                .expect("email should contain a token")
+                .to_string()
+        })
+        .collect()
+}
+
+async fn is_verified(db: &PgPool, username: &str) -> bool {
+    sqlx::query_scalar::<_, bool>(
+        r#"select email_verified_at is not null from "user" where username = $1"#,
+    )
+    .bind(username)
+    .fetch_one(db)
+    .await
+    .unwrap()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn verify_email(db: PgPool) {
+    let (app, dir) = app_with_mail_dir(db.clone());
+    app.create_user("john").await;
+
+    assert!(!is_verified(&db, "john").await);
+
+    let tokens = mailed_tokens(&dir, "john@example.com");
+    assert_eq!(tokens.len(), 1);
+
+    let res = app
+        .post(
+            "/api/users/verify",
+            None,
+            json!({"user": {"token": tokens[0]}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["user"]["username"], "john");
+    assert!(is_verified(&db, "john").await);
+
+    // tokens are single-use
+    let res = app
+        .post(
+            "/api/users/verify",
+            None,
+            json!({"user": {"token": tokens[0]}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        res.body,
+        json!({"errors": {"token": ["invalid or expired"]}})
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn reset_password(db: PgPool) {
+    let (app, dir) = app_with_mail_dir(db);
+    app.create_user("john").await;
+
+    let res = app
+        .post(
+            "/api/users/password-reset",
+            None,
+            json!({"user": {"email": "john@example.com"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+
+    let res = app
+        .post(
+            "/api/users/password-reset",
+            None,
+            json!({"user": {"email": "john@example.com"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+
+    // the first email is the verification one
+    let tokens = mailed_tokens(&dir, "john@example.com");
+    assert_eq!(tokens.len(), 3);
+
+    // a verification token can't be used to reset the password
+    let res = app
+        .post(
+            "/api/users/password-reset/confirm",
+            None,
+            json!({"user": {"token": tokens[0], "password": "new-password"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let res = app
+        .post(
+            "/api/users/password-reset/confirm",
+            None,
+            json!({"user": {"token": tokens[2], "password": "new-password"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["user"]["username"], "john");
+
+    let res = app
+        .post(
+            "/api/users/login",
+            None,
+            json!({"user": {"email": "john@example.com", "password": "new-password"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+
+    // resetting the password retires the other outstanding reset tokens
+    let res = app
+        .post(
+            "/api/users/password-reset/confirm",
+            None,
+            json!({"user": {"token": tokens[1], "password": "another-password"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn reset_password_for_unknown_email(db: PgPool) {
+    let (app, dir) = app_with_mail_dir(db);
+
+    let res = app
+        .post(
+            "/api/users/password-reset",
+            None,
+            json!({"user": {"email": "nobody@example.com"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+    assert!(mailed_tokens(&dir, "nobody@example.com").is_empty());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn emails_stay_in_the_mail_dir(db: PgPool) {
+    let (app, dir) = app_with_mail_dir(db);
+    let to = "john/../../x@example.com";
+
+    let res = app
+        .post(
+            "/api/users",
+            None,
+            json!({"user": {"username": "john", "email": to, "password": "password123"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(mailed_tokens(&dir, to).len(), 1);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn expired_tokens_are_rejected(db: PgPool) {
+    let (app, dir) = app_with_mail_dir(db.clone());
+    app.create_user("john").await;
+
+    sqlx::query("update user_token set expires_at = now() - interval '1 second'")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let token = &mailed_tokens(&dir, "john@example.com")[0];
+    let res = app
+        .post("/api/users/verify", None, json!({"user": {"token": token}}))
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!is_verified(&db, "john").await);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn changing_email_requires_verifying_it_again(db: PgPool) {
+    let (app, dir) = app_with_mail_dir(db.clone());
+    let token = app.create_user("john").await;
+
+    // verified some other way, with the token mailed at signup still unused
+    let old_token = &mailed_tokens(&dir, "john@example.com")[0];
+    sqlx::query(r#"update "user" set email_verified_at = now()"#)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    // sending the same address isn't a change
+    let res = app
+        .put(
+            "/api/user",
+            Some(&token),
+            json!({"user": {"email": "john@example.com"}}),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(is_verified(&db, "john").await);
+    assert_eq!(mailed_tokens(&dir, "john@example.com").len(), 1);
+
+    let res = app
+        .put(
+            "/api/user",
+            Some(&token),
+            json!({"user": {"email": "john@example.org"}}),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["user"]["email"], "john@example.org");
+    assert!(!is_verified(&db, "john").await);
+
+    // the token mailed to the old address no longer verifies anything
+    let res = app
+        .post(
+            "/api/users/verify",
+            None,
+            json!({"user": {"token": old_token}}),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!is_verified(&db, "john").await);
+
+    let new_tokens = mailed_tokens(&dir, "john@example.org");
+    assert_eq!(new_tokens.len(), 1);
+
+    let res = app
+        .post(
+            "/api/users/verify",
+            None,
+            json!({"user": {"token": new_tokens[0]}}),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(is_verified(&db, "john").await);
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use axum::http::header::WWW_AUTHENTICATE;
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+
+use common::TestApp;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn create_and_login_user(db: PgPool) {
+    let app = TestApp::new(db);
+
+    let res = app
+        .post(
+            "/api/users",
+            None,
+            json!({"user": {"username": "john", "email": "john@example.com", "password": "password123"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["user"]["username"], "john");
+    assert_eq!(res.body["user"]["email"], "john@example.com");
+    assert_eq!(res.body["user"]["bio"], "");
+    assert!(res.body["user"]["image"].is_null());
+    assert!(res.body["user"]["token"].is_string());
+
+    let res = app
+        .post(
+            "/api/users/login",
+            None,
+            json!({"user": {"email": "john@example.com", "password": "password123"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["user"]["username"], "john");
+    assert!(res.body["user"]["token"].is_string());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn create_user_with_taken_username_or_email(db: PgPool) {
+    let app = TestApp::new(db);
+    app.create_user("john").await;
+
+    let res = app
+        .post(
+            "/api/users",
+            None,
+            json!({"user": {"username": "JOHN", "email": "other@example.com", "password": "password123"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        res.body,
+        json!({"errors": {"username": ["username taken"]}})
+    );
+
+    let res = app
+        .post(
+            "/api/users",
+            None,
+            json!({"user": {"username": "other", "email": "john@example.com", "password": "password123"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.body, json!({"errors": {"email": ["email taken"]}}));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn login_unknown_email(db: PgPool) {
+    let app = TestApp::new(db);
+
+    let res = app
+        .post(
+            "/api/users/login",
+            None,
+            json!({"user": {"email": "nobody@example.com", "password": "password123"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.body, json!({"errors": {"email": ["does not exist"]}}));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn current_user_requires_token(db: PgPool) {
+    let app = TestApp::new(db);
+
+    let res = app.get("/api/user", None).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.headers[WWW_AUTHENTICATE], "Token");
+    assert_eq!(res.body, "authentication required");
+
+    let res = app.get("/api/user", Some("not-a-jwt")).await;
+
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.headers[WWW_AUTHENTICATE], "Token");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn get_and_update_current_user(db: PgPool) {
+    let app = TestApp::new(db);
+    let token = app.create_user("john").await;
+
+    let res = app.get("/api/user", Some(&token)).await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["user"]["username"], "john");
+
+    let res = app
+        .put(
+            "/api/user",
+            Some(&token),
+            json!({"user": {"bio": "hello", "image": "https://example.com/john.png"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["user"]["username"], "john");
+    assert_eq!(res.body["user"]["bio"], "hello");
+    assert_eq!(res.body["user"]["image"], "https://example.com/john.png");
+
+    app.create_user("jane").await;
+
+    let res = app
+        .put(
+            "/api/user",
+            Some(&token),
+            json!({"user": {"username": "jane"}}),
+        )
+        .await;
+
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        res.body,
+        json!({"errors": {"username": ["username taken"]}})
+    );
+}